@@ -20,6 +20,11 @@ impl AABB {
     }
 
     pub fn hit(&self, ray: &Ray, tmin: f32, tmax: f32) -> bool {
+        self.interval(ray, tmin, tmax).is_some()
+    }
+
+    /// The interval of `ray` between `tmin` and `tmax` inside the box.
+    pub fn interval(&self, ray: &Ray, tmin: f32, tmax: f32) -> Option<(f32, f32)> {
         let t0 = (self.min - ray.origin()) / ray.direction();
         let t1 = (self.max - ray.origin()) / ray.direction();
 
@@ -29,7 +34,7 @@ impl AABB {
         let t_enter = tmin_vec.max_element().max(tmin);
         let t_exit = tmax_vec.min_element().min(tmax);
 
-        t_enter < t_exit
+        (t_enter < t_exit).then_some((t_enter, t_exit))
     }
 
     pub fn surrounding_box(box0: &AABB, box1: &AABB) -> AABB {
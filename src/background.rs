@@ -0,0 +1,25 @@
+use glam::Vec3A;
+
+use crate::{pdf::ConePDF, sky::Sky, spectrum, vec::Color};
+
+/// What a ray sees when it escapes the scene.
+#[derive(Clone)]
+pub enum Background {
+    Sky(Sky),
+}
+
+impl Background {
+    pub fn value(&self, direction: Vec3A) -> Color {
+        spectrum::from_rgb(match self {
+            Background::Sky(s) => s.value(direction),
+        })
+    }
+
+    /// A pdf over the directions of compact emitters in the background, if
+    /// any. A sun at or below the horizon is not worth sampling.
+    pub fn sun_pdf(&self) -> Option<ConePDF> {
+        match self {
+            Background::Sky(s) => (s.sun_direction().y > 0.0).then(|| s.sun_pdf()),
+        }
+    }
+}
@@ -0,0 +1,1151 @@
+//! Scattering functions evaluated in a local shading frame.
+//!
+//! A `BxDF` works with directions in the frame of an `ONB` whose +z is the
+//! shading normal, and a `BSDF` pairs it with that frame to work in world
+//! space. `wo` points back along the path towards where it came from and
+//! `wi` towards where it continues. Values include the cosine between `wi`
+//! and the normal, so that a path's throughput is multiplied by `f / pdf`;
+//! in participating media they are the phase function.
+
+use std::{
+    f32,
+    ops::{BitOr, BitOrAssign},
+};
+
+use glam::{Vec2, Vec3A};
+
+use crate::{
+    microfacet::{
+        ThinFilm, TrowbridgeReitz, fresnel_conductor, fresnel_dielectric, generalized_half_vector,
+        transmit,
+    },
+    onb::ONB,
+    vec::Color,
+};
+
+/// The kinds of scattering a `BxDF` can do, or that a sample did.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct BSDFFlags(u8);
+
+impl BSDFFlags {
+    pub const REFLECTION: Self = Self(1);
+    pub const TRANSMISSION: Self = Self(1 << 1);
+    pub const DIFFUSE: Self = Self(1 << 2);
+    pub const GLOSSY: Self = Self(1 << 3);
+    /// Scattering into a single direction, which can only be sampled.
+    pub const SPECULAR: Self = Self(1 << 4);
+
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn is_specular(self) -> bool {
+        self.contains(Self::SPECULAR)
+    }
+
+    /// Whether some of the scattering can be evaluated for given directions,
+    /// as needed to connect paths or sample lights.
+    pub fn is_non_specular(self) -> bool {
+        self.0 & (Self::DIFFUSE.0 | Self::GLOSSY.0) != 0
+    }
+}
+
+impl BitOr for BSDFFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for BSDFFlags {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+/// What a path scattering at a `BSDF` carries: `Radiance` for paths traced
+/// from the camera, `Importance` for paths traced from the lights. Radiance
+/// is scaled by the squared ratio of the refractive indices when it crosses
+/// an interface between dielectrics, importance is not (Veach 1997, 5.2).
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TransportMode {
+    Radiance,
+    Importance,
+}
+
+/// A direction sampled by a `BxDF`, with the value of the scattering
+/// function for it and the solid angle density it was sampled with. Both
+/// are relative to a discrete probability for specular samples.
+pub struct BSDFSample {
+    pub wi: Vec3A,
+    pub f: Color,
+    pub pdf: f32,
+    pub flags: BSDFFlags,
+}
+
+/// A scattering function at a hit, in world space.
+#[derive(Clone)]
+pub struct BSDF {
+    frame: ONB,
+    bxdf: BxDF,
+}
+
+impl BSDF {
+    pub fn new(frame: ONB, bxdf: BxDF) -> Self {
+        Self { frame, bxdf }
+    }
+
+    pub fn frame(&self) -> ONB {
+        self.frame
+    }
+
+    pub fn bxdf(&self) -> &BxDF {
+        &self.bxdf
+    }
+
+    pub fn flags(&self) -> BSDFFlags {
+        self.bxdf.flags()
+    }
+
+    pub fn eval(&self, wo: Vec3A, wi: Vec3A, mode: TransportMode) -> Color {
+        self.bxdf.eval(self.to_local(wo), self.to_local(wi), mode)
+    }
+
+    /// Samples a direction for the path to continue in, with `u` uniform
+    /// over the unit cube.
+    pub fn sample(&self, wo: Vec3A, u: Vec3A, mode: TransportMode) -> Option<BSDFSample> {
+        let sample = self.bxdf.sample(self.to_local(wo), u, mode)?;
+        Some(BSDFSample {
+            wi: self.frame.local(sample.wi),
+            ..sample
+        })
+    }
+
+    /// The density with which `sample` produces `wi` from `wo`, zero for
+    /// specular scattering.
+    pub fn pdf(&self, wo: Vec3A, wi: Vec3A) -> f32 {
+        self.bxdf.pdf(self.to_local(wo), self.to_local(wi))
+    }
+
+    fn to_local(&self, w: Vec3A) -> Vec3A {
+        self.frame.to_local(w.normalize())
+    }
+}
+
+#[derive(Clone)]
+pub enum BxDF {
+    Diffuse(DiffuseBxDF),
+    OrenNayar(OrenNayarBxDF),
+    Metal(MetalBxDF),
+    Conductor(ConductorBxDF),
+    Dielectric(DielectricBxDF),
+    RoughDielectric(RoughDielectricBxDF),
+    Principled(PrincipledBxDF),
+    Coated(CoatedBxDF),
+    HenyeyGreenstein(HenyeyGreensteinBxDF),
+}
+
+impl BxDF {
+    pub fn flags(&self) -> BSDFFlags {
+        match self {
+            BxDF::Diffuse(b) => b.flags(),
+            BxDF::OrenNayar(b) => b.flags(),
+            BxDF::Metal(b) => b.flags(),
+            BxDF::Conductor(b) => b.flags(),
+            BxDF::Dielectric(b) => b.flags(),
+            BxDF::RoughDielectric(b) => b.flags(),
+            BxDF::Principled(b) => b.flags(),
+            BxDF::Coated(b) => b.flags(),
+            BxDF::HenyeyGreenstein(b) => b.flags(),
+        }
+    }
+
+    /// The color given to the scattering function: the albedo of diffuse
+    /// lobes and media, the reflectance of conductors at normal incidence and
+    /// white for dielectrics. Coats show the color of their base.
+    pub fn albedo(&self) -> Color {
+        match self {
+            BxDF::Diffuse(b) => b.albedo,
+            BxDF::OrenNayar(b) => b.albedo,
+            BxDF::Metal(b) => b.albedo,
+            BxDF::Conductor(b) => b.fresnel(1.0),
+            BxDF::Dielectric(_) | BxDF::RoughDielectric(_) => Color::ONE,
+            BxDF::Principled(b) => b.base,
+            BxDF::Coated(b) => b.base.albedo(),
+            BxDF::HenyeyGreenstein(b) => b.albedo,
+        }
+    }
+
+    pub fn eval(&self, wo: Vec3A, wi: Vec3A, mode: TransportMode) -> Color {
+        match self {
+            BxDF::Diffuse(b) => b.eval(wo, wi),
+            BxDF::OrenNayar(b) => b.eval(wo, wi),
+            BxDF::Metal(_) | BxDF::Dielectric(_) => Color::ZERO,
+            BxDF::Conductor(b) => b.eval(wo, wi),
+            BxDF::RoughDielectric(b) => b.eval(wo, wi, mode),
+            BxDF::Principled(b) => b.eval(wo, wi, mode),
+            BxDF::Coated(b) => b.eval(wo, wi, mode),
+            BxDF::HenyeyGreenstein(b) => b.eval(wo, wi),
+        }
+    }
+
+    pub fn sample(&self, wo: Vec3A, u: Vec3A, mode: TransportMode) -> Option<BSDFSample> {
+        match self {
+            BxDF::Diffuse(b) => b.sample(wo, u),
+            BxDF::OrenNayar(b) => b.sample(wo, u),
+            BxDF::Metal(b) => b.sample(wo, u),
+            BxDF::Conductor(b) => b.sample(wo, u),
+            BxDF::Dielectric(b) => b.sample(wo, u, mode),
+            BxDF::RoughDielectric(b) => b.sample(wo, u, mode),
+            BxDF::Principled(b) => b.sample(wo, u, mode),
+            BxDF::Coated(b) => b.sample(wo, u, mode),
+            BxDF::HenyeyGreenstein(b) => b.sample(wo, u),
+        }
+    }
+
+    pub fn pdf(&self, wo: Vec3A, wi: Vec3A) -> f32 {
+        match self {
+            BxDF::Diffuse(b) => b.pdf(wo, wi),
+            BxDF::OrenNayar(b) => b.pdf(wo, wi),
+            BxDF::Metal(_) | BxDF::Dielectric(_) => 0.0,
+            BxDF::Conductor(b) => b.pdf(wo, wi),
+            BxDF::RoughDielectric(b) => b.pdf(wo, wi),
+            BxDF::Principled(b) => b.pdf(wo, wi),
+            BxDF::Coated(b) => b.pdf(wo, wi),
+            BxDF::HenyeyGreenstein(b) => b.pdf(wo, wi),
+        }
+    }
+}
+
+/// Lambertian reflection on the side of `wo`.
+#[derive(Clone, Copy)]
+pub struct DiffuseBxDF {
+    albedo: Color,
+}
+
+impl DiffuseBxDF {
+    pub fn new(albedo: Color) -> Self {
+        Self { albedo }
+    }
+
+    pub fn flags(&self) -> BSDFFlags {
+        BSDFFlags::DIFFUSE | BSDFFlags::REFLECTION
+    }
+
+    pub fn eval(&self, wo: Vec3A, wi: Vec3A) -> Color {
+        self.albedo * self.pdf(wo, wi)
+    }
+
+    pub fn sample(&self, wo: Vec3A, u: Vec3A) -> Option<BSDFSample> {
+        let mut wi = sample_cosine_hemisphere(Vec2::new(u.y, u.z));
+        if wo.z < 0.0 {
+            wi.z = -wi.z;
+        }
+        let pdf = self.pdf(wo, wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(BSDFSample {
+            wi,
+            f: self.albedo * pdf,
+            pdf,
+            flags: self.flags(),
+        })
+    }
+
+    pub fn pdf(&self, wo: Vec3A, wi: Vec3A) -> f32 {
+        if wo.z * wi.z <= 0.0 {
+            return 0.0;
+        }
+        wi.z.abs() / f32::consts::PI
+    }
+}
+
+/// Reflection off a rough diffuse surface of V-shaped Lambertian facets,
+/// with the qualitative model of Oren and Nayar (1994). It is sampled like
+/// `DiffuseBxDF`.
+#[derive(Clone, Copy)]
+pub struct OrenNayarBxDF {
+    albedo: Color,
+    a: f32,
+    b: f32,
+}
+
+impl OrenNayarBxDF {
+    /// `sigma` is the standard deviation of the facet slope angle in
+    /// radians; zero gives Lambertian reflection.
+    pub fn new(albedo: Color, sigma: f32) -> Self {
+        let sigma2 = sigma * sigma;
+        Self {
+            albedo,
+            a: 1.0 - sigma2 / (2.0 * (sigma2 + 0.33)),
+            b: 0.45 * sigma2 / (sigma2 + 0.09),
+        }
+    }
+
+    pub fn flags(&self) -> BSDFFlags {
+        BSDFFlags::DIFFUSE | BSDFFlags::REFLECTION
+    }
+
+    pub fn eval(&self, wo: Vec3A, wi: Vec3A) -> Color {
+        if wo.z * wi.z <= 0.0 {
+            return Color::ZERO;
+        }
+        let sin_i = f32::sqrt((1.0 - wi.z * wi.z).max(0.0));
+        let sin_o = f32::sqrt((1.0 - wo.z * wo.z).max(0.0));
+        // The cosine of the azimuth between the directions.
+        let cos_phi = if sin_i > 1e-4 && sin_o > 1e-4 {
+            ((wi.x * wo.x + wi.y * wo.y) / (sin_i * sin_o)).max(0.0)
+        } else {
+            0.0
+        };
+        let (sin_alpha, tan_beta) = if wi.z.abs() > wo.z.abs() {
+            (sin_o, sin_i / wi.z.abs())
+        } else {
+            (sin_i, sin_o / wo.z.abs())
+        };
+        self.albedo / f32::consts::PI
+            * (self.a + self.b * cos_phi * sin_alpha * tan_beta)
+            * wi.z.abs()
+    }
+
+    pub fn sample(&self, wo: Vec3A, u: Vec3A) -> Option<BSDFSample> {
+        let mut wi = sample_cosine_hemisphere(Vec2::new(u.y, u.z));
+        if wo.z < 0.0 {
+            wi.z = -wi.z;
+        }
+        let pdf = self.pdf(wo, wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(BSDFSample {
+            wi,
+            f: self.eval(wo, wi),
+            pdf,
+            flags: self.flags(),
+        })
+    }
+
+    pub fn pdf(&self, wo: Vec3A, wi: Vec3A) -> f32 {
+        if wo.z * wi.z <= 0.0 {
+            return 0.0;
+        }
+        wi.z.abs() / f32::consts::PI
+    }
+}
+
+/// The fuzzy mirror of RTOW: the mirror direction perturbed by a random
+/// point in a sphere of radius `fuzz`, treated as specular.
+#[derive(Clone, Copy)]
+pub struct MetalBxDF {
+    albedo: Color,
+    fuzz: f32,
+}
+
+impl MetalBxDF {
+    pub fn new(albedo: Color, fuzz: f32) -> Self {
+        Self { albedo, fuzz }
+    }
+
+    pub fn flags(&self) -> BSDFFlags {
+        BSDFFlags::SPECULAR | BSDFFlags::REFLECTION
+    }
+
+    pub fn sample(&self, wo: Vec3A, u: Vec3A) -> Option<BSDFSample> {
+        let offset = u.x.cbrt() * sample_uniform_sphere(Vec2::new(u.y, u.z));
+        let wi = (reflect(wo) + self.fuzz * offset).normalize_or_zero();
+        if wi == Vec3A::ZERO {
+            return None;
+        }
+        Some(BSDFSample {
+            wi,
+            f: self.albedo,
+            pdf: 1.0,
+            flags: self.flags(),
+        })
+    }
+}
+
+/// A metal with GGX microfacets and complex refractive index `eta + i k`,
+/// or a perfect mirror when the distribution is smooth.
+#[derive(Clone, Copy)]
+pub struct ConductorBxDF {
+    eta: Color,
+    k: Color,
+    distribution: TrowbridgeReitz,
+    film: Option<ThinFilm>,
+}
+
+impl ConductorBxDF {
+    pub fn new(eta: Color, k: Color, distribution: TrowbridgeReitz) -> Self {
+        Self {
+            eta,
+            k,
+            distribution,
+            film: None,
+        }
+    }
+
+    pub fn with_thin_film(self, film: ThinFilm) -> Self {
+        Self {
+            film: Some(film),
+            ..self
+        }
+    }
+
+    fn fresnel(&self, cos_theta: f32) -> Color {
+        match self.film {
+            Some(film) => film.reflectance(cos_theta, self.eta, self.k),
+            None => fresnel_conductor(cos_theta, self.eta, self.k),
+        }
+    }
+
+    pub fn flags(&self) -> BSDFFlags {
+        if self.distribution.is_smooth() {
+            BSDFFlags::SPECULAR | BSDFFlags::REFLECTION
+        } else {
+            BSDFFlags::GLOSSY | BSDFFlags::REFLECTION
+        }
+    }
+
+    pub fn eval(&self, wo: Vec3A, wi: Vec3A) -> Color {
+        if wo.z <= 0.0 || wi.z <= 0.0 || self.distribution.is_smooth() {
+            return Color::ZERO;
+        }
+        let wm = (wo + wi).normalize_or_zero();
+        if wm == Vec3A::ZERO {
+            return Color::ZERO;
+        }
+        let d = self.distribution.d(wm);
+        let g = self.distribution.g(wo, wi);
+        self.fresnel(wo.dot(wm)) * d * g / (4.0 * wo.z)
+    }
+
+    pub fn sample(&self, wo: Vec3A, u: Vec3A) -> Option<BSDFSample> {
+        if wo.z <= 0.0 {
+            return None;
+        }
+        if self.distribution.is_smooth() {
+            return Some(BSDFSample {
+                wi: reflect(wo),
+                f: self.fresnel(wo.z),
+                pdf: 1.0,
+                flags: self.flags(),
+            });
+        }
+        let wm = self.distribution.sample_wm(wo, Vec2::new(u.y, u.z));
+        let wi = (-wo).reflect(wm);
+        let pdf = self.pdf(wo, wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(BSDFSample {
+            wi,
+            f: self.eval(wo, wi),
+            pdf,
+            flags: self.flags(),
+        })
+    }
+
+    pub fn pdf(&self, wo: Vec3A, wi: Vec3A) -> f32 {
+        if self.distribution.is_smooth() {
+            return 0.0;
+        }
+        reflection_pdf(&self.distribution, wo, wi)
+    }
+}
+
+/// A smooth interface between dielectrics that chooses between reflection
+/// and refraction with Schlick's approximation of the Fresnel equations, or
+/// the reflectance of a thin film on top of it. `eta` is the ratio of the
+/// refractive index below the surface to the one above. Transmitted
+/// radiance is scaled as described at `TransportMode`.
+#[derive(Clone, Copy)]
+pub struct DielectricBxDF {
+    eta: f32,
+    film: Option<ThinFilm>,
+}
+
+impl DielectricBxDF {
+    pub fn new(eta: f32) -> Self {
+        Self { eta, film: None }
+    }
+
+    pub fn with_thin_film(self, film: ThinFilm) -> Self {
+        Self {
+            film: Some(film),
+            ..self
+        }
+    }
+
+    pub fn flags(&self) -> BSDFFlags {
+        BSDFFlags::SPECULAR | BSDFFlags::REFLECTION | BSDFFlags::TRANSMISSION
+    }
+
+    pub fn sample(&self, wo: Vec3A, u: Vec3A, mode: TransportMode) -> Option<BSDFSample> {
+        let (n, etai_over_etat) = if wo.z < 0.0 {
+            (-Vec3A::Z, self.eta)
+        } else {
+            (Vec3A::Z, 1.0 / self.eta)
+        };
+        let reflection = |f: Color, pdf: f32| BSDFSample {
+            wi: reflect(wo),
+            f,
+            pdf,
+            flags: BSDFFlags::SPECULAR | BSDFFlags::REFLECTION,
+        };
+
+        let cos_theta = wo.dot(n).min(1.0);
+        let sin_theta = f32::sqrt(1.0 - cos_theta * cos_theta);
+        if etai_over_etat * sin_theta > 1.0 {
+            return Some(reflection(Color::ONE, 1.0));
+        }
+
+        // A film reflects each wavelength differently, so the choice follows
+        // the mean reflectance.
+        let reflectance = match self.film {
+            Some(film) => {
+                let film = if wo.z < 0.0 {
+                    film.from_below(self.eta)
+                } else {
+                    film
+                };
+                film.reflectance(cos_theta, Color::splat(1.0 / etai_over_etat), Color::ZERO)
+            }
+            None => Color::splat(schlick(cos_theta, etai_over_etat)),
+        };
+        let reflect_prob = reflectance.element_sum() / 3.0;
+        if u.x < reflect_prob {
+            return Some(reflection(reflectance, reflect_prob));
+        }
+        let scale = match mode {
+            TransportMode::Radiance => etai_over_etat * etai_over_etat,
+            TransportMode::Importance => 1.0,
+        };
+        Some(BSDFSample {
+            wi: (-wo).refract(n, etai_over_etat),
+            f: (Color::ONE - reflectance) * scale,
+            pdf: 1.0 - reflect_prob,
+            flags: BSDFFlags::SPECULAR | BSDFFlags::TRANSMISSION,
+        })
+    }
+}
+
+/// A rough interface between dielectrics with GGX microfacets that both
+/// reflect and transmit light (Walter et al. 2007), with `eta` the ratio of
+/// the refractive index below the surface to the one above.
+#[derive(Clone, Copy)]
+pub struct RoughDielectricBxDF {
+    eta: f32,
+    distribution: TrowbridgeReitz,
+}
+
+impl RoughDielectricBxDF {
+    pub fn new(eta: f32, distribution: TrowbridgeReitz) -> Self {
+        Self { eta, distribution }
+    }
+
+    pub fn flags(&self) -> BSDFFlags {
+        BSDFFlags::GLOSSY | BSDFFlags::REFLECTION | BSDFFlags::TRANSMISSION
+    }
+
+    pub fn eval(&self, wo: Vec3A, wi: Vec3A, mode: TransportMode) -> Color {
+        Color::splat(self.distribution.dielectric_f(wo, wi, self.eta, mode) * wi.z.abs())
+    }
+
+    pub fn sample(&self, wo: Vec3A, u: Vec3A, mode: TransportMode) -> Option<BSDFSample> {
+        let wi = sample_dielectric(&self.distribution, wo, self.eta, u);
+        let pdf = self.pdf(wo, wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+        let flags = if wo.z * wi.z > 0.0 {
+            BSDFFlags::GLOSSY | BSDFFlags::REFLECTION
+        } else {
+            BSDFFlags::GLOSSY | BSDFFlags::TRANSMISSION
+        };
+        Some(BSDFSample {
+            wi,
+            f: self.eval(wo, wi, mode),
+            pdf,
+            flags,
+        })
+    }
+
+    pub fn pdf(&self, wo: Vec3A, wi: Vec3A) -> f32 {
+        dielectric_pdf(&self.distribution, wo, wi, self.eta)
+    }
+}
+
+/// Disney's principled BSDF with its parameters evaluated at a hit, in a
+/// frame whose +z points out of the surface. The weights of the lobes also
+/// set how often each is sampled.
+#[derive(Clone, Copy)]
+pub struct PrincipledBxDF {
+    pub base: Color,
+    pub sheen: Color,
+    /// Specular reflectance at normal incidence.
+    pub specular0: Color,
+    pub roughness: f32,
+    pub diffuse_weight: f32,
+    pub specular_weight: f32,
+    pub glass_weight: f32,
+    pub clearcoat_weight: f32,
+    pub eta: f32,
+    pub distribution: TrowbridgeReitz,
+}
+
+/// The microfacets of the clear coat of `PrincipledBxDF`.
+const CLEARCOAT: TrowbridgeReitz = TrowbridgeReitz::new(0.05, 0.05);
+
+impl PrincipledBxDF {
+    pub fn flags(&self) -> BSDFFlags {
+        let mut flags = BSDFFlags::GLOSSY | BSDFFlags::REFLECTION;
+        if self.diffuse_weight > 0.0 {
+            flags |= BSDFFlags::DIFFUSE;
+        }
+        if self.glass_weight > 0.0 {
+            flags |= BSDFFlags::TRANSMISSION;
+        }
+        flags
+    }
+
+    pub fn eval(&self, wo: Vec3A, wi: Vec3A, mode: TransportMode) -> Color {
+        let glass = self.glass_weight * self.distribution.dielectric_f(wo, wi, self.eta, mode);
+        let mut f = if wo.z * wi.z < 0.0 {
+            // Tinted on both crossings, giving the base color through a
+            // closed object.
+            self.base.map(f32::sqrt) * glass
+        } else {
+            Color::splat(glass)
+        };
+
+        if wo.z > 0.0 && wi.z > 0.0 {
+            let wh = (wo + wi).normalize();
+            let cos_d = wi.dot(wh);
+
+            let fd90 = 0.5 + 2.0 * self.roughness * cos_d * cos_d;
+            let retro = (1.0 + (fd90 - 1.0) * schlick_weight(wi.z))
+                * (1.0 + (fd90 - 1.0) * schlick_weight(wo.z));
+            let diffuse = self.base / f32::consts::PI * retro + self.sheen * schlick_weight(cos_d);
+
+            let fresnel = self.specular0.lerp(Color::ONE, schlick_weight(wo.dot(wh)));
+            let specular = fresnel * self.distribution.d(wh) * self.distribution.g(wo, wi)
+                / (4.0 * wo.z * wi.z);
+
+            let fresnel = 0.04 + 0.96 * schlick_weight(wo.dot(wh));
+            let clearcoat = fresnel * CLEARCOAT.d(wh) * CLEARCOAT.g(wo, wi) / (4.0 * wo.z * wi.z);
+
+            f += self.diffuse_weight * diffuse
+                + self.specular_weight * specular
+                + self.clearcoat_weight * clearcoat;
+        }
+
+        f * wi.z.abs()
+    }
+
+    /// The weights with which the diffuse, specular, glass and clear coat
+    /// lobes are sampled. Only the glass lobe scatters light arriving from
+    /// inside.
+    fn lobe_weights(&self, wo: Vec3A) -> [f32; 4] {
+        let weights = if wo.z > 0.0 {
+            [
+                self.diffuse_weight,
+                self.specular_weight,
+                self.glass_weight,
+                self.clearcoat_weight,
+            ]
+        } else {
+            [0.0, 0.0, self.glass_weight, 0.0]
+        };
+        let total: f32 = weights.iter().sum();
+        if total <= 0.0 {
+            return [0.0; 4];
+        }
+        weights.map(|w| w / total)
+    }
+
+    pub fn sample(&self, wo: Vec3A, u: Vec3A, mode: TransportMode) -> Option<BSDFSample> {
+        let weights = self.lobe_weights(wo);
+        let (mut lobe, mut uc) = (None, u.x);
+        for (i, &w) in weights.iter().enumerate() {
+            if w > 0.0 {
+                lobe = Some(i);
+                if uc < w {
+                    uc /= w;
+                    break;
+                }
+            }
+            uc -= w;
+        }
+        let u2 = Vec2::new(u.y, u.z);
+        let wi = match lobe? {
+            0 => sample_cosine_hemisphere(u2),
+            1 => (-wo).reflect(self.distribution.sample_wm(wo, u2)),
+            2 => sample_dielectric(
+                &self.distribution,
+                wo,
+                self.eta,
+                Vec3A::new(uc.clamp(0.0, 1.0), u.y, u.z),
+            ),
+            _ => (-wo).reflect(CLEARCOAT.sample_wm(wo, u2)),
+        };
+
+        let pdf = self.pdf(wo, wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+        let flags = match (lobe?, wo.z * wi.z > 0.0) {
+            (0, _) => BSDFFlags::DIFFUSE | BSDFFlags::REFLECTION,
+            (_, true) => BSDFFlags::GLOSSY | BSDFFlags::REFLECTION,
+            (_, false) => BSDFFlags::GLOSSY | BSDFFlags::TRANSMISSION,
+        };
+        Some(BSDFSample {
+            wi,
+            f: self.eval(wo, wi, mode),
+            pdf,
+            flags,
+        })
+    }
+
+    pub fn pdf(&self, wo: Vec3A, wi: Vec3A) -> f32 {
+        let [diffuse, specular, glass, clearcoat] = self.lobe_weights(wo);
+        let mut pdf = glass * dielectric_pdf(&self.distribution, wo, wi, self.eta);
+        if wo.z > 0.0 && wi.z > 0.0 {
+            pdf += diffuse * wi.z / f32::consts::PI
+                + specular * reflection_pdf(&self.distribution, wo, wi)
+                + clearcoat * reflection_pdf(&CLEARCOAT, wo, wi);
+        }
+        pdf
+    }
+}
+
+/// A smooth dielectric coat over another `BxDF` (Weidlich and Wilkie 2007).
+/// Light either reflects off the coat or refracts through it, scatters once
+/// off the base and leaves through the coat, attenuated by absorption in the
+/// coat on the way. Light arriving from below the surface is not scattered.
+#[derive(Clone)]
+pub struct CoatedBxDF {
+    /// The refractive index of the coat relative to the outside.
+    eta: f32,
+    /// The optical depth of the coat along its normal.
+    depth: Color,
+    base: Box<BxDF>,
+}
+
+impl CoatedBxDF {
+    pub fn new(eta: f32, depth: Color, base: BxDF) -> Self {
+        Self {
+            eta,
+            depth,
+            base: Box::new(base),
+        }
+    }
+
+    pub fn flags(&self) -> BSDFFlags {
+        self.base.flags() | BSDFFlags::SPECULAR | BSDFFlags::REFLECTION
+    }
+
+    /// The direction inside the coat of light refracting along `w` outside.
+    fn refract_in(&self, w: Vec3A) -> Vec3A {
+        let (x, y) = (w.x / self.eta, w.y / self.eta);
+        Vec3A::new(x, y, f32::sqrt((1.0 - x * x - y * y).max(0.0)))
+    }
+
+    /// The direction outside of light refracting along `w` inside the coat,
+    /// `None` on total internal reflection.
+    fn refract_out(&self, w: Vec3A) -> Option<Vec3A> {
+        let (x, y) = (w.x * self.eta, w.y * self.eta);
+        let cos2 = 1.0 - x * x - y * y;
+        (w.z > 0.0 && cos2 > 0.0).then(|| Vec3A::new(x, y, cos2.sqrt()))
+    }
+
+    /// The fraction of light crossing the coat down along `wo` and up along
+    /// `wi`, both inside the coat, that is not absorbed.
+    fn transmittance(&self, wo: Vec3A, wi: Vec3A) -> Color {
+        (-self.depth * (1.0 / wo.z + 1.0 / wi.z)).exp()
+    }
+
+    /// The change of solid angle density from `wi_inside` to `wi`, which
+    /// also accounts for the radiance of light leaving the coat.
+    fn jacobian(&self, wi: Vec3A, wi_inside: Vec3A) -> f32 {
+        wi.z / (self.eta * self.eta * wi_inside.z)
+    }
+
+    pub fn eval(&self, wo: Vec3A, wi: Vec3A, mode: TransportMode) -> Color {
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return Color::ZERO;
+        }
+        let (wo_inside, wi_inside) = (self.refract_in(wo), self.refract_in(wi));
+        let t =
+            (1.0 - fresnel_dielectric(wo.z, self.eta)) * (1.0 - fresnel_dielectric(wi.z, self.eta));
+        self.base.eval(wo_inside, wi_inside, mode)
+            * self.transmittance(wo_inside, wi_inside)
+            * t
+            * self.jacobian(wi, wi_inside)
+    }
+
+    pub fn sample(&self, wo: Vec3A, u: Vec3A, mode: TransportMode) -> Option<BSDFSample> {
+        if wo.z <= 0.0 {
+            return None;
+        }
+        let r = fresnel_dielectric(wo.z, self.eta);
+        if u.x < r {
+            return Some(BSDFSample {
+                wi: reflect(wo),
+                f: Color::splat(r),
+                pdf: r,
+                flags: BSDFFlags::SPECULAR | BSDFFlags::REFLECTION,
+            });
+        }
+
+        let wo_inside = self.refract_in(wo);
+        let u = Vec3A::new((u.x - r) / (1.0 - r), u.y, u.z);
+        let sample = self.base.sample(wo_inside, u, mode)?;
+        let wi = self.refract_out(sample.wi)?;
+        // Specular samples are already relative to a discrete probability.
+        let jacobian = if sample.flags.is_specular() {
+            1.0
+        } else {
+            self.jacobian(wi, sample.wi)
+        };
+        let t = (1.0 - r) * (1.0 - fresnel_dielectric(wi.z, self.eta));
+        Some(BSDFSample {
+            wi,
+            f: sample.f * self.transmittance(wo_inside, sample.wi) * t * jacobian,
+            pdf: (1.0 - r) * sample.pdf * jacobian,
+            flags: sample.flags,
+        })
+    }
+
+    pub fn pdf(&self, wo: Vec3A, wi: Vec3A) -> f32 {
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return 0.0;
+        }
+        let (wo_inside, wi_inside) = (self.refract_in(wo), self.refract_in(wi));
+        (1.0 - fresnel_dielectric(wo.z, self.eta))
+            * self.base.pdf(wo_inside, wi_inside)
+            * self.jacobian(wi, wi_inside)
+    }
+}
+
+/// The Henyey-Greenstein phase function of a medium, scattering forward for
+/// positive `g` and backward for negative `g`. The frame is irrelevant.
+#[derive(Clone, Copy)]
+pub struct HenyeyGreensteinBxDF {
+    albedo: Color,
+    g: f32,
+}
+
+impl HenyeyGreensteinBxDF {
+    pub fn new(albedo: Color, g: f32) -> Self {
+        Self { albedo, g }
+    }
+
+    pub fn flags(&self) -> BSDFFlags {
+        BSDFFlags::DIFFUSE | BSDFFlags::REFLECTION | BSDFFlags::TRANSMISSION
+    }
+
+    pub fn eval(&self, wo: Vec3A, wi: Vec3A) -> Color {
+        self.albedo * self.pdf(wo, wi)
+    }
+
+    pub fn sample(&self, wo: Vec3A, u: Vec3A) -> Option<BSDFSample> {
+        let g = self.g;
+        let cos_theta = if g.abs() < 1e-3 {
+            1.0 - 2.0 * u.y
+        } else {
+            let sqr_term = (1.0 - g * g) / (1.0 - g + 2.0 * g * u.y);
+            (1.0 + g * g - sqr_term * sqr_term) / (2.0 * g)
+        };
+        let sin_theta = f32::sqrt((1.0 - cos_theta * cos_theta).max(0.0));
+        let phi = 2.0 * f32::consts::PI * u.z;
+        let wi = ONB::build_from_w(-wo).local(Vec3A::new(
+            phi.cos() * sin_theta,
+            phi.sin() * sin_theta,
+            cos_theta,
+        ));
+        let pdf = self.pdf(wo, wi);
+        Some(BSDFSample {
+            wi,
+            f: self.albedo * pdf,
+            pdf,
+            flags: self.flags(),
+        })
+    }
+
+    pub fn pdf(&self, wo: Vec3A, wi: Vec3A) -> f32 {
+        henyey_greenstein(-wo.dot(wi), self.g)
+    }
+}
+
+/// The Henyey-Greenstein phase function for the cosine between the
+/// incoming and scattered directions of travel.
+pub fn henyey_greenstein(cos_theta: f32, g: f32) -> f32 {
+    let denom = 1.0 + g * g - 2.0 * g * cos_theta;
+    (1.0 - g * g) / (4.0 * f32::consts::PI * denom * denom.sqrt())
+}
+
+/// Cosine-weighted directions on the hemisphere around +z.
+pub fn sample_cosine_hemisphere(u: Vec2) -> Vec3A {
+    let r = u.x.sqrt();
+    let phi = 2.0 * f32::consts::PI * u.y;
+    Vec3A::new(r * phi.cos(), r * phi.sin(), f32::sqrt(1.0 - u.x))
+}
+
+pub fn sample_uniform_sphere(u: Vec2) -> Vec3A {
+    let z = 1.0 - 2.0 * u.x;
+    let r = f32::sqrt((1.0 - z * z).max(0.0));
+    let phi = 2.0 * f32::consts::PI * u.y;
+    Vec3A::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+/// The mirror direction of `w` about +z.
+fn reflect(w: Vec3A) -> Vec3A {
+    Vec3A::new(-w.x, -w.y, w.z)
+}
+
+/// The density of reflecting `wo` into `wi` off a microfacet visible from
+/// `wo`.
+fn reflection_pdf(distribution: &TrowbridgeReitz, wo: Vec3A, wi: Vec3A) -> f32 {
+    if wi.z * wo.z <= 0.0 {
+        return 0.0;
+    }
+    let wm = (wo + wi).normalize_or_zero();
+    if wm == Vec3A::ZERO {
+        return 0.0;
+    }
+    distribution.d_visible(wo, wm) / (4.0 * wo.dot(wm).abs())
+}
+
+/// Reflects or transmits `wo` through a visible microfacet of a rough
+/// dielectric, choosing by the Fresnel reflectance with `u.x`.
+fn sample_dielectric(distribution: &TrowbridgeReitz, wo: Vec3A, eta: f32, u: Vec3A) -> Vec3A {
+    let wm = distribution.sample_wm(wo, Vec2::new(u.y, u.z));
+    let reflected = (-wo).reflect(wm);
+    if u.x < fresnel_dielectric(wo.dot(wm), eta) {
+        reflected
+    } else {
+        transmit(wo, wm, eta).unwrap_or(reflected)
+    }
+}
+
+/// The density with which `sample_dielectric` produces `wi`.
+fn dielectric_pdf(distribution: &TrowbridgeReitz, wo: Vec3A, wi: Vec3A, eta: f32) -> f32 {
+    let Some((wm, etap)) = generalized_half_vector(wo, wi, eta) else {
+        return 0.0;
+    };
+    let r = fresnel_dielectric(wo.dot(wm), eta);
+    let d = distribution.d_visible(wo, wm);
+    if etap == 1.0 {
+        d / (4.0 * wo.dot(wm).abs()) * r
+    } else {
+        let denom = (wi.dot(wm) + wo.dot(wm) / etap).powi(2);
+        d * wi.dot(wm).abs() / denom * (1.0 - r)
+    }
+}
+
+/// The weight of Schlick's approximation of the Fresnel equations.
+fn schlick_weight(cos: f32) -> f32 {
+    (1.0 - cos).clamp(0.0, 1.0).powi(5)
+}
+
+fn schlick(cos: f32, ref_idx: f32) -> f32 {
+    let mut r0 = (1.0 - ref_idx) / (1.0 + ref_idx);
+    r0 = r0 * r0;
+    r0 + (1.0 - r0) * (1.0 - cos).powi(5)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec::Vec3Ext;
+
+    fn direction(theta: f32, phi: f32) -> Vec3A {
+        Vec3A::new(
+            theta.sin() * phi.cos(),
+            theta.sin() * phi.sin(),
+            theta.cos(),
+        )
+    }
+
+    fn bxdfs() -> Vec<(&'static str, BxDF)> {
+        let rough = TrowbridgeReitz::new(0.3, 0.3);
+        let anisotropic = TrowbridgeReitz::new(0.1, 0.5);
+        let gold = (
+            Color::new(0.143, 0.374, 1.442),
+            Color::new(3.983, 2.385, 1.603),
+        );
+        vec![
+            ("diffuse", BxDF::Diffuse(DiffuseBxDF::new(Color::ONE))),
+            (
+                "oren-nayar",
+                BxDF::OrenNayar(OrenNayarBxDF::new(Color::ONE, 0.5)),
+            ),
+            (
+                "conductor",
+                BxDF::Conductor(ConductorBxDF::new(gold.0, gold.1, rough)),
+            ),
+            (
+                "anisotropic conductor",
+                BxDF::Conductor(ConductorBxDF::new(gold.0, gold.1, anisotropic)),
+            ),
+            (
+                "rough dielectric",
+                BxDF::RoughDielectric(RoughDielectricBxDF::new(1.5, rough)),
+            ),
+            (
+                "principled",
+                BxDF::Principled(PrincipledBxDF {
+                    base: Color::new(0.8, 0.5, 0.3),
+                    sheen: Color::splat(0.1),
+                    specular0: Color::splat(0.04),
+                    roughness: 0.5,
+                    diffuse_weight: 0.25,
+                    specular_weight: 0.75,
+                    glass_weight: 0.25,
+                    clearcoat_weight: 0.25,
+                    eta: 1.5,
+                    distribution: rough,
+                }),
+            ),
+            (
+                "coated",
+                BxDF::Coated(CoatedBxDF::new(
+                    1.5,
+                    Color::splat(0.1),
+                    BxDF::Diffuse(DiffuseBxDF::new(Color::ONE)),
+                )),
+            ),
+            (
+                "henyey-greenstein",
+                BxDF::HenyeyGreenstein(HenyeyGreensteinBxDF::new(Color::ONE, 0.6)),
+            ),
+        ]
+    }
+
+    /// Outgoing directions above and below the surface, from near normal
+    /// to grazing.
+    fn outgoing() -> impl Iterator<Item = Vec3A> {
+        [0.2, 1.0, 1.4]
+            .into_iter()
+            .flat_map(|theta| [direction(theta, 0.7), -direction(theta, 0.3)])
+    }
+
+    #[test]
+    fn pdfs_integrate_to_at_most_one() {
+        const N: usize = 256;
+        for (name, bxdf) in bxdfs() {
+            for wo in outgoing() {
+                let mut integral = 0.0;
+                for i in 0..N {
+                    for j in 0..N {
+                        let u = (Vec2::new(i as f32, j as f32) + 0.5) / N as f32;
+                        integral += bxdf.pdf(wo, sample_uniform_sphere(u));
+                    }
+                }
+                integral *= 4.0 * f32::consts::PI / (N * N) as f32;
+                assert!(
+                    integral <= 1.02,
+                    "{name}: pdf integrates to {integral} for wo = {wo}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn samples_match_eval_and_pdf() {
+        for (name, bxdf) in bxdfs() {
+            for wo in outgoing() {
+                for mode in [TransportMode::Radiance, TransportMode::Importance] {
+                    for _ in 0..2000 {
+                        let u = Vec3A::random_range(0.0..1.0);
+                        let Some(sample) = bxdf.sample(wo, u, mode) else {
+                            continue;
+                        };
+                        if sample.flags.is_specular() {
+                            continue;
+                        }
+                        let pdf = bxdf.pdf(wo, sample.wi);
+                        let f = bxdf.eval(wo, sample.wi, mode);
+                        assert!(
+                            (pdf - sample.pdf).abs() <= 1e-3 * pdf.max(1.0),
+                            "{name}: sampled pdf {} but pdf {pdf}",
+                            sample.pdf
+                        );
+                        assert!(
+                            f.abs_diff_eq(sample.f, 1e-3 * f.max_element().max(1.0)),
+                            "{name}: sampled f {} but eval {f}",
+                            sample.f
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn albedos_are_at_most_one() {
+        const N: usize = 20_000;
+        for (name, bxdf) in bxdfs() {
+            for wo in outgoing() {
+                let mut albedo = Color::ZERO;
+                for _ in 0..N {
+                    let u = Vec3A::random_range(0.0..1.0);
+                    if let Some(sample) = bxdf.sample(wo, u, TransportMode::Importance) {
+                        albedo += sample.f / sample.pdf;
+                    }
+                }
+                albedo /= N as f32;
+                assert!(
+                    albedo.max_element() <= 1.02,
+                    "{name}: albedo {albedo} for wo = {wo}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn rough_dielectric_never_gains_energy() {
+        let bxdf = RoughDielectricBxDF::new(1.5, TrowbridgeReitz::new(0.3, 0.3));
+        for wo in [0.2, 1.0, 1.4, 2.0, 2.8].map(|theta| direction(theta, 0.7)) {
+            for _ in 0..10_000 {
+                let u = Vec3A::random_range(0.0..1.0);
+                let Some(sample) = bxdf.sample(wo, u, TransportMode::Importance) else {
+                    continue;
+                };
+                let weight = sample.f.x / sample.pdf;
+                assert!(weight <= 1.0 + 1e-3, "{weight} for wo = {wo}");
+            }
+        }
+    }
+
+    #[test]
+    fn refraction_scales_radiance_only() {
+        let smooth = DielectricBxDF::new(1.5);
+        let rough = RoughDielectricBxDF::new(1.5, TrowbridgeReitz::new(0.3, 0.3));
+        // Entering the denser side, and leaving it.
+        for (wo, scale) in [
+            (direction(0.3, 0.7), 1.0 / (1.5 * 1.5)),
+            (-direction(0.3, 0.7), 1.5 * 1.5),
+        ] {
+            let radiance = rough.eval(wo, -wo, TransportMode::Radiance);
+            let importance = rough.eval(wo, -wo, TransportMode::Importance);
+            assert!(importance.x > 0.0);
+            assert!(radiance.abs_diff_eq(importance * scale, 1e-4 * radiance.x));
+
+            for _ in 0..100 {
+                let u = Vec3A::random_range(0.0..1.0);
+                let radiance = smooth.sample(wo, u, TransportMode::Radiance).unwrap();
+                let importance = smooth.sample(wo, u, TransportMode::Importance).unwrap();
+                let expected = if radiance.flags.contains(BSDFFlags::TRANSMISSION) {
+                    importance.f * scale
+                } else {
+                    importance.f
+                };
+                assert!(radiance.f.abs_diff_eq(expected, 1e-5));
+            }
+        }
+    }
+}
@@ -1,8 +1,9 @@
 use crate::{
     aabb::AABB,
-    hittable::{Geometry, HitRecord},
+    hittable::{FreeFlightPdf, Geometry, HitFn, HitRecord},
     rand,
     ray::Ray,
+    vec::Color,
 };
 use std::cmp::Ordering;
 
@@ -58,18 +59,47 @@ impl BVHBranch {
     }
 
     pub fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<(f32, HitRecord)> {
+        self.closest_hit(ray, t_min, t_max, Geometry::hit)
+    }
+
+    pub fn hit_surface(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<(f32, HitRecord)> {
+        self.closest_hit(ray, t_min, t_max, Geometry::hit_surface)
+    }
+
+    fn closest_hit(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+        hit: HitFn,
+    ) -> Option<(f32, HitRecord)> {
         if !self.bx.hit(ray, t_min, t_max) {
             return None;
         }
 
-        let hit_left = self.left.hit(ray, t_min, t_max);
+        let hit_left = hit(&self.left, ray, t_min, t_max);
 
         let t_max_for_right = hit_left.as_ref().map_or(t_max, |(t, _)| *t);
-        let hit_right = self.right.hit(ray, t_min, t_max_for_right);
+        let hit_right = hit(&self.right, ray, t_min, t_max_for_right);
 
         hit_right.or(hit_left)
     }
 
+    pub fn transmittance(&self, ray: &Ray, t_min: f32, t_max: f32) -> Color {
+        if !self.bx.hit(ray, t_min, t_max) {
+            return Color::ONE;
+        }
+        self.left.transmittance(ray, t_min, t_max) * self.right.transmittance(ray, t_min, t_max)
+    }
+
+    pub fn free_flight_pdf(&self, ray: &Ray, t_min: f32, t_max: f32) -> FreeFlightPdf {
+        if !self.bx.hit(ray, t_min, t_max) {
+            return FreeFlightPdf::VACUUM;
+        }
+        let left = self.left.free_flight_pdf(ray, t_min, t_max);
+        left.and(self.right.free_flight_pdf(ray, t_min, t_max))
+    }
+
     pub fn bounding_box(&self) -> Option<AABB> {
         Some(self.bx.clone())
     }
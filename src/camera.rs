@@ -9,9 +9,25 @@ pub struct Camera {
     horizontal: Vec3A,
     vertical: Vec3A,
     lens_radius: f32,
+    focus_dist: f32,
 
     u: Vec3A,
     v: Vec3A,
+    w: Vec3A,
+}
+
+/// A point seen through the camera, found by `Camera::project`.
+pub struct Projection {
+    /// The point on the lens the ray to the projected point starts from.
+    pub lens_point: Point3,
+    /// Image coordinates in the convention of `Camera::get_ray`.
+    pub s: f32,
+    pub t: f32,
+    /// Importance of the ray times the cosine at the lens over the squared
+    /// distance, divided by the density of `lens_point`. The importance is
+    /// normalized to integrate to one over the unit square of image
+    /// coordinates; the cosine at `p` is left to the caller.
+    pub weight: f32,
 }
 
 impl Camera {
@@ -43,9 +59,11 @@ impl Camera {
             horizontal,
             vertical,
             lens_radius: aperture / 2.0,
+            focus_dist,
 
             u,
             v,
+            w,
         }
     }
 
@@ -57,4 +75,82 @@ impl Camera {
             self.lower_left_corner + s * self.horizontal + t * self.vertical - self.origin - offset,
         )
     }
+
+    /// The inverse of `get_ray`: samples a point on the lens and finds the
+    /// image coordinates of the ray from it through `p`, if `p` is in view.
+    pub fn project(&self, p: Point3) -> Option<Projection> {
+        let rd = self.lens_radius * Vec3A::random_in_unit_disk();
+        let lens_point = self.origin + self.u * rd.x + self.v * rd.y;
+
+        let to_p = p - lens_point;
+        let distance_squared = to_p.length_squared();
+        let direction = to_p / distance_squared.sqrt();
+        let cos_theta = -direction.dot(self.w);
+        if cos_theta <= 0.0 {
+            return None;
+        }
+
+        // Where the ray crosses the plane in focus, which `get_ray` spans.
+        let q = lens_point + direction * (self.focus_dist / cos_theta);
+        let s =
+            (q - self.lower_left_corner).dot(self.horizontal) / self.horizontal.length_squared();
+        let t = (q - self.lower_left_corner).dot(self.vertical) / self.vertical.length_squared();
+
+        // Solid angle per unit of image coordinates is A cos^3 theta, with A
+        // the image area at unit distance.
+        let area =
+            self.horizontal.length() * self.vertical.length() / (self.focus_dist * self.focus_dist);
+        Some(Projection {
+            lens_point,
+            s,
+            t,
+            weight: 1.0 / (area * cos_theta.powi(3) * distance_squared),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rand;
+
+    /// A pinhole camera looking down -z with an image of 1 x 2 at
+    /// unit distance.
+    fn camera() -> Camera {
+        Camera::new(Point3::ZERO, -Vec3A::Z, Vec3A::Y, 90.0, 0.5, 0.0, 1.0)
+    }
+
+    #[test]
+    fn project_forward_point() {
+        let projection = camera().project(Point3::new(0.0, 0.0, -3.0)).unwrap();
+        assert_eq!(projection.lens_point, Point3::ZERO);
+        assert!((projection.s - 0.5).abs() < 1e-6);
+        assert!((projection.t - 0.5).abs() < 1e-6);
+        let area = 2.0;
+        let expected = 1.0 / (area * 3.0 * 3.0);
+        assert!((projection.weight - expected).abs() < 1e-6 * expected);
+    }
+
+    #[test]
+    fn project_inverts_get_ray() {
+        let camera = camera();
+        let area = 2.0;
+        for _ in 0..100 {
+            let (s, t) = (rand::random::<f32>(), rand::random::<f32>());
+            let ray = camera.get_ray(s, t);
+            let direction = ray.direction().normalize();
+            let p = ray.at(rand::random_range(0.5..5.0));
+            let projection = camera.project(p).unwrap();
+            assert!((projection.s - s).abs() < 1e-4);
+            assert!((projection.t - t).abs() < 1e-4);
+
+            // The weight times the solid angle per unit of image coordinates
+            // and the squared distance is one, so weights integrate to one
+            // over the image.
+            let cos_theta = -direction.dot(Vec3A::Z);
+            let distance_squared = (p - *ray.origin()).length_squared();
+            let total = projection.weight * area * cos_theta.powi(3) * distance_squared;
+            assert!((total - 1.0).abs() < 1e-4);
+        }
+    }
 }
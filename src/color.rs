@@ -1,5 +1,7 @@
 use std::io::Write;
 
+use glam::Vec3A;
+
 use crate::vec::Color;
 
 pub fn write_color(w: &mut impl Write, mut pixel_color: Color, samples_per_pixel: i32) {
@@ -16,3 +18,17 @@ pub fn write_color(w: &mut impl Write, mut pixel_color: Color, samples_per_pixel
 
     writeln!(w, "{} {} {}", r as u8, g as u8, b as u8).unwrap();
 }
+
+/// Converts CIE XYZ to linear sRGB (D65 white point).
+pub fn xyz_to_rgb(xyz: Vec3A) -> Color {
+    Color::new(
+        3.240479 * xyz.x - 1.53715 * xyz.y - 0.498535 * xyz.z,
+        -0.969256 * xyz.x + 1.875991 * xyz.y + 0.041556 * xyz.z,
+        0.055648 * xyz.x - 0.204043 * xyz.y + 1.057311 * xyz.z,
+    )
+}
+
+/// Luminance (CIE Y) of a linear sRGB color.
+pub fn luminance(c: Color) -> f32 {
+    0.2126 * c.x + 0.7152 * c.y + 0.0722 * c.z
+}
@@ -0,0 +1,177 @@
+use std::sync::Arc;
+
+use glam::Vec3A;
+
+use crate::{
+    aabb::AABB,
+    hittable::{FreeFlightPdf, Geometry, HitRecord},
+    material::{Isotropic, Material},
+    rand,
+    ray::Ray,
+    texture::Texture,
+};
+
+/// A homogeneous participating medium filling a closed boundary shape.
+///
+/// Rays are scattered at exponentially distributed distances inside the
+/// boundary, so integrators see the medium as a surface hit whose material
+/// is the phase function. The boundary may be concave or made of several
+/// pieces, such as a BVH of shapes, as long as the pieces do not overlap.
+pub struct ConstantMedium {
+    boundary: Box<Geometry>,
+    neg_inv_density: f32,
+    phase_function: Material,
+}
+
+impl ConstantMedium {
+    pub fn new(boundary: Geometry, density: f32, albedo: Arc<dyn Texture>) -> Self {
+        Self::with_phase_function(
+            boundary,
+            density,
+            Material::Isotropic(Isotropic::new(albedo)),
+        )
+    }
+
+    /// A medium scattering with `phase_function`, which should be a medium
+    /// material such as `Isotropic` or `HenyeyGreenstein`.
+    pub fn with_phase_function(boundary: Geometry, density: f32, phase_function: Material) -> Self {
+        Self {
+            boundary: Box::new(boundary),
+            neg_inv_density: -1.0 / density,
+            phase_function,
+        }
+    }
+
+    pub fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<(f32, HitRecord)> {
+        // The distance to travel inside, spent over the intervals in turn.
+        let mut hit_distance = self.neg_inv_density * f32::ln(1.0 - rand::random::<f32>());
+        let mut hit = None;
+        inside_intervals(&self.boundary, ray, t_min, t_max, |t1, t2| {
+            if hit_distance < t2 - t1 {
+                hit = Some(t1 + hit_distance);
+                return true;
+            }
+            hit_distance -= t2 - t1;
+            false
+        });
+        let t = hit?;
+        // The normal and orientation are arbitrary inside a medium.
+        let rec = HitRecord::new(
+            ray.at(t),
+            Vec3A::X,
+            self.phase_function.clone(),
+            0.0,
+            0.0,
+            true,
+        );
+        Some((t, rec))
+    }
+
+    /// The probability of `ray` passing through the medium between `t_min`
+    /// and `t_max`.
+    pub fn transmittance(&self, ray: &Ray, t_min: f32, t_max: f32) -> f32 {
+        self.free_flight_pdf(ray, t_min, t_max).pass
+    }
+
+    pub fn free_flight_pdf(&self, ray: &Ray, t_min: f32, t_max: f32) -> FreeFlightPdf {
+        let mut distance_inside = 0.0;
+        let mut inside_at_end = false;
+        inside_intervals(&self.boundary, ray, t_min, t_max, |t1, t2| {
+            distance_inside += t2 - t1;
+            inside_at_end = t2 >= t_max;
+            false
+        });
+        let pass = f32::exp(distance_inside / self.neg_inv_density);
+        let scatter = if inside_at_end {
+            -pass / self.neg_inv_density
+        } else {
+            0.0
+        };
+        FreeFlightPdf { pass, scatter }
+    }
+
+    pub fn bounding_box(&self) -> Option<AABB> {
+        self.boundary.bounding_box()
+    }
+}
+
+/// Calls `f` with each interval of `ray` between `t_min` and `t_max` that
+/// lies inside the closed `boundary`, in order, until `f` returns true.
+/// Returns whether it did. Consecutive crossings of the boundary are paired
+/// into intervals, so it may be concave or made of several pieces as long
+/// as they do not overlap.
+pub(crate) fn inside_intervals(
+    boundary: &Geometry,
+    ray: &Ray,
+    t_min: f32,
+    t_max: f32,
+    mut f: impl FnMut(f32, f32) -> bool,
+) -> bool {
+    let mut t = f32::NEG_INFINITY;
+    while let Some((t1, _)) = boundary.hit(ray, t, f32::INFINITY) {
+        if t1 >= t_max {
+            break;
+        }
+        let Some((t2, _)) = boundary.hit(ray, t1 + 0.0001, f32::INFINITY) else {
+            break;
+        };
+        let (start, end) = (t1.max(t_min), t2.min(t_max));
+        if start < end && f(start, end) {
+            return true;
+        }
+        t = t2 + 0.0001;
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{hittable::HittableList, sphere::Sphere, texture::SolidTexture, vec::Point3};
+
+    /// Unit spheres at x = -3 and x = 3 with density 0.5.
+    fn separate_pieces() -> ConstantMedium {
+        let albedo = Arc::new(SolidTexture::new(1.0, 1.0, 1.0));
+        let material = Material::Isotropic(Isotropic::new(albedo.clone()));
+        let mut pieces = HittableList::new();
+        for x in [-3.0, 3.0] {
+            pieces.add(Geometry::Sphere(Sphere::new(
+                Point3::new(x, 0.0, 0.0),
+                1.0,
+                material.clone(),
+            )));
+        }
+        ConstantMedium::new(Geometry::List(pieces), 0.5, albedo)
+    }
+
+    #[test]
+    fn transmittance_through_separate_pieces() {
+        let medium = separate_pieces();
+        let ray = Ray::new(Point3::new(-5.0, 0.0, 0.0), Vec3A::X);
+        let expected = f32::exp(-0.5 * 4.0);
+        assert!((medium.transmittance(&ray, 0.0, f32::INFINITY) - expected).abs() < 1e-4);
+        // Only the first piece lies before t = 4.
+        let expected = f32::exp(-0.5 * 2.0);
+        assert!((medium.transmittance(&ray, 0.0, 4.0) - expected).abs() < 1e-4);
+    }
+
+    #[test]
+    fn free_flight_pdf_matches_sampled_distances() {
+        let medium = separate_pieces();
+        let ray = Ray::new(Point3::new(-5.0, 0.0, 0.0), Vec3A::X);
+
+        const N: usize = 200_000;
+        let (mut beyond, mut near) = (0, 0);
+        for _ in 0..N {
+            match medium.hit(&ray, 0.0, f32::INFINITY) {
+                Some((t, _)) if t < 8.0 => near += (t > 7.9) as usize,
+                _ => beyond += 1,
+            }
+        }
+        let pdf = medium.free_flight_pdf(&ray, 0.0, 8.0);
+        assert!((beyond as f32 / N as f32 - pdf.pass).abs() < 0.01);
+        assert!((near as f32 / N as f32 / 0.1 - pdf.scatter).abs() < 0.01);
+        // Scattering is impossible between the pieces.
+        assert_eq!(medium.free_flight_pdf(&ray, 0.0, 5.0).scatter, 0.0);
+    }
+}
@@ -0,0 +1,103 @@
+use std::{
+    io::Write,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use crate::{color, vec::Color};
+
+/// The image being rendered, holding the radiance estimate of each pixel.
+pub struct Film {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+}
+
+impl Film {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![Color::ZERO; width * height],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Pixels in rows from the top of the image to the bottom.
+    pub fn pixels(&self) -> &[Color] {
+        &self.pixels
+    }
+
+    pub fn pixels_mut(&mut self) -> &mut [Color] {
+        &mut self.pixels
+    }
+
+    pub fn write_ppm(&self, w: &mut impl Write) {
+        write!(w, "P3\n{} {}\n255\n", self.width, self.height).unwrap();
+        for pixel_color in &self.pixels {
+            color::write_color(w, *pixel_color, 1);
+        }
+    }
+}
+
+/// An image that many threads can add contributions to at arbitrary pixels,
+/// for integrators that do not know which pixel a sample lands in until it
+/// has been traced.
+pub struct SplatFilm {
+    width: usize,
+    height: usize,
+    /// Color channels stored as `f32` bits.
+    pixels: Vec<[AtomicU32; 3]>,
+}
+
+impl SplatFilm {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: (0..width * height)
+                .map(|_| std::array::from_fn(|_| AtomicU32::new(0.0f32.to_bits())))
+                .collect(),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Adds `color` to pixel (`i`, `j`), counting rows from the bottom of
+    /// the image.
+    pub fn splat(&self, i: usize, j: usize, color: Color) {
+        let pixel = &self.pixels[(self.height - 1 - j) * self.width + i];
+        for (channel, value) in pixel.iter().zip(color.to_array()) {
+            if value == 0.0 {
+                continue;
+            }
+            channel
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+                    Some((f32::from_bits(bits) + value).to_bits())
+                })
+                .unwrap();
+        }
+    }
+
+    /// Writes the splatted sums times `scale` into `film`.
+    pub fn resolve(&self, film: &mut Film, scale: f32) {
+        for (color, pixel) in film.pixels_mut().iter_mut().zip(&self.pixels) {
+            let [r, g, b] = pixel
+                .each_ref()
+                .map(|c| f32::from_bits(c.load(Ordering::Relaxed)));
+            *color = Color::new(r, g, b) * scale;
+        }
+    }
+}
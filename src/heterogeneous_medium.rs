@@ -0,0 +1,189 @@
+use std::sync::Arc;
+
+use glam::Vec3A;
+
+use crate::{
+    aabb::AABB,
+    constant_medium::inside_intervals,
+    hittable::{FreeFlightPdf, Geometry, HitRecord},
+    material::Material,
+    rand,
+    ray::Ray,
+    texture::Texture,
+    vec::Point3,
+    voxel_grid::VoxelGrid,
+};
+
+/// A participating medium filling a closed boundary shape, whose density
+/// varies in space.
+///
+/// The density at a point is the mean of the channels of a 3D texture. Free
+/// paths are sampled with delta tracking and transmittance is estimated with
+/// ratio tracking, both against `max_density`, which must bound the density
+/// everywhere inside the boundary.
+pub struct HeterogeneousMedium {
+    boundary: Boundary,
+    density: Arc<dyn Texture>,
+    max_density: f32,
+    phase_function: Material,
+}
+
+impl HeterogeneousMedium {
+    /// `phase_function` should be a medium material such as `Isotropic` or
+    /// `HenyeyGreenstein`.
+    pub fn new(
+        boundary: Geometry,
+        density: Arc<dyn Texture>,
+        max_density: f32,
+        phase_function: Material,
+    ) -> Self {
+        Self {
+            boundary: Boundary::Shape(Box::new(boundary)),
+            density,
+            max_density,
+            phase_function,
+        }
+    }
+
+    /// A medium with the densities of `grid`, bounded by the box of the grid.
+    pub fn from_grid(grid: VoxelGrid, phase_function: Material) -> Self {
+        Self {
+            boundary: Boundary::Box(grid.bounding_box()),
+            max_density: grid.max_density(),
+            density: Arc::new(grid),
+            phase_function,
+        }
+    }
+
+    fn density(&self, p: Point3) -> f32 {
+        self.density.value(0.0, 0.0, p).element_sum() / 3.0
+    }
+
+    /// Steps through the medium with exponential distances against the
+    /// majorant, calling `f` with the ratio of the real density at each
+    /// step. Returns the position of the step at which `f` returned true.
+    fn track(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+        mut f: impl FnMut(f32) -> bool,
+    ) -> Option<f32> {
+        let mut stop = None;
+        self.boundary.intervals(ray, t_min, t_max, |mut t, t_end| {
+            loop {
+                t -= f32::ln(1.0 - rand::random::<f32>()) / self.max_density;
+                if t >= t_end {
+                    return false;
+                }
+                if f(self.density(ray.at(t)) / self.max_density) {
+                    stop = Some(t);
+                    return true;
+                }
+            }
+        });
+        stop
+    }
+
+    /// Delta tracking: real collisions are accepted with the ratio of the
+    /// real density to the majorant, the rest are null collisions.
+    pub fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<(f32, HitRecord)> {
+        let t = self.track(ray, t_min, t_max, |ratio| rand::random::<f32>() < ratio)?;
+
+        // The normal and orientation are arbitrary inside a medium.
+        let rec = HitRecord::new(
+            ray.at(t),
+            Vec3A::X,
+            self.phase_function.clone(),
+            0.0,
+            0.0,
+            true,
+        );
+        Some((t, rec))
+    }
+
+    /// Ratio tracking estimate of the probability of `ray` passing through
+    /// the medium between `t_min` and `t_max`.
+    pub fn transmittance(&self, ray: &Ray, t_min: f32, t_max: f32) -> f32 {
+        let mut transmittance = 1.0;
+        self.track(ray, t_min, t_max, |ratio| {
+            transmittance *= 1.0 - ratio.min(1.0);
+            transmittance <= 0.0
+        });
+        transmittance
+    }
+
+    /// The densities of delta tracking, with the optical depth integrated by
+    /// the midpoint rule rather than estimated by ratio tracking, so that they
+    /// are the same on every call.
+    pub fn free_flight_pdf(&self, ray: &Ray, t_min: f32, t_max: f32) -> FreeFlightPdf {
+        const STEPS: usize = 16;
+        let mut optical_depth = 0.0;
+        let mut inside_at_end = false;
+        self.boundary.intervals(ray, t_min, t_max, |t1, t2| {
+            let dt = (t2 - t1) / STEPS as f32;
+            optical_depth += (0..STEPS)
+                .map(|i| self.density(ray.at(t1 + (i as f32 + 0.5) * dt)) * dt)
+                .sum::<f32>();
+            inside_at_end = t2 >= t_max;
+            false
+        });
+        let pass = f32::exp(-optical_depth);
+        let scatter = if inside_at_end {
+            self.density(ray.at(t_max)) * pass
+        } else {
+            0.0
+        };
+        FreeFlightPdf { pass, scatter }
+    }
+
+    pub fn bounding_box(&self) -> Option<AABB> {
+        match &self.boundary {
+            Boundary::Shape(shape) => shape.bounding_box(),
+            Boundary::Box(bx) => Some(bx.clone()),
+        }
+    }
+}
+
+enum Boundary {
+    Shape(Box<Geometry>),
+    Box(AABB),
+}
+
+impl Boundary {
+    /// Calls `f` with the intervals of `ray` inside, as `inside_intervals`.
+    fn intervals(&self, ray: &Ray, t_min: f32, t_max: f32, mut f: impl FnMut(f32, f32) -> bool) {
+        match self {
+            Boundary::Shape(shape) => {
+                inside_intervals(shape, ray, t_min, t_max, f);
+            }
+            Boundary::Box(bx) => {
+                if let Some((t1, t2)) = bx.interval(ray, t_min, t_max) {
+                    f(t1, t2);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{material::Isotropic, sphere::Sphere, texture::SolidTexture};
+
+    #[test]
+    fn free_flight_pdf_of_uniform_density() {
+        let albedo = Arc::new(SolidTexture::new(1.0, 1.0, 1.0));
+        let phase_function = Material::Isotropic(Isotropic::new(albedo));
+        let boundary = Geometry::Sphere(Sphere::new(Point3::ZERO, 1.0, phase_function.clone()));
+        let density = Arc::new(SolidTexture::new(2.0, 2.0, 2.0));
+        let medium = HeterogeneousMedium::new(boundary, density, 2.0, phase_function);
+
+        // Halfway through the sphere, one unit inside.
+        let ray = Ray::new(Point3::new(-5.0, 0.0, 0.0), Vec3A::X);
+        let pdf = medium.free_flight_pdf(&ray, 0.0, 5.0);
+        assert!((pdf.pass - f32::exp(-2.0)).abs() < 1e-4);
+        assert!((pdf.scatter - 2.0 * f32::exp(-2.0)).abs() < 1e-4);
+        assert_eq!(pdf.pass, medium.free_flight_pdf(&ray, 0.0, 5.0).pass);
+    }
+}
@@ -1,8 +1,16 @@
-
 use glam::Vec3A;
 
 use crate::{
-    aabb::AABB, bvh::BVHBranch, material::Material, rand, ray::Ray, sphere::Sphere, vec::Point3,
+    aabb::AABB,
+    bvh::BVHBranch,
+    constant_medium::ConstantMedium,
+    heterogeneous_medium::HeterogeneousMedium,
+    material::{Material, RefractiveIndex},
+    rand,
+    ray::Ray,
+    sphere::Sphere,
+    subsurface::Subsurface,
+    vec::{Color, Point3},
 };
 
 pub struct HitRecord {
@@ -12,6 +20,15 @@ pub struct HitRecord {
     u: f32,
     v: f32,
     front_face: bool,
+    /// The derivative of the point with respect to `u`, orienting
+    /// anisotropic materials. Zero where the surface has no such direction.
+    dpdu: Vec3A,
+    /// Refractive indices on the incident and transmitted side, if known
+    /// from medium tracking.
+    iors: Option<(RefractiveIndex, RefractiveIndex)>,
+    /// Factor of the path throughput from sampling the distance to the hit
+    /// where the density is not proportional to the transmittance.
+    weight: Color,
 }
 
 impl HitRecord {
@@ -30,9 +47,20 @@ impl HitRecord {
             u,
             v,
             front_face,
+            dpdu: Vec3A::ZERO,
+            iors: None,
+            weight: Color::ONE,
         }
     }
 
+    pub fn with_dpdu(self, dpdu: Vec3A) -> Self {
+        Self { dpdu, ..self }
+    }
+
+    pub fn with_weight(self, weight: Color) -> Self {
+        Self { weight, ..self }
+    }
+
     pub fn point(&self) -> Point3 {
         self.p
     }
@@ -56,8 +84,54 @@ impl HitRecord {
     pub fn front_face(&self) -> bool {
         self.front_face
     }
+
+    pub fn dpdu(&self) -> Vec3A {
+        self.dpdu
+    }
+
+    pub fn iors(&self) -> Option<(RefractiveIndex, RefractiveIndex)> {
+        self.iors
+    }
+
+    pub fn weight(&self) -> Color {
+        self.weight
+    }
+
+    pub fn set_iors(&mut self, incident: RefractiveIndex, transmitted: RefractiveIndex) {
+        self.iors = Some((incident, transmitted));
+    }
+}
+
+/// Densities of the distances participating media sample along a ray,
+/// computed without random numbers so that integrators can use them in MIS
+/// weights.
+#[derive(Clone, Copy, Debug)]
+pub struct FreeFlightPdf {
+    /// The probability of reaching `t_max` without scattering.
+    pub pass: f32,
+    /// The density of scattering at `t_max`.
+    pub scatter: f32,
 }
 
+impl FreeFlightPdf {
+    pub const VACUUM: Self = Self {
+        pass: 1.0,
+        scatter: 0.0,
+    };
+
+    /// The densities of `self` and `other` sampling their distances
+    /// independently, the nearest of which is used.
+    pub fn and(self, other: Self) -> Self {
+        Self {
+            pass: self.pass * other.pass,
+            scatter: self.scatter * other.pass + self.pass * other.scatter,
+        }
+    }
+}
+
+/// One of the ways of intersecting a `Geometry`, such as `Geometry::hit`.
+pub(crate) type HitFn = fn(&Geometry, &Ray, f32, f32) -> Option<(f32, HitRecord)>;
+
 #[derive(Default)]
 pub struct HittableList {
     objects: Vec<Geometry>,
@@ -72,12 +146,30 @@ impl HittableList {
         self.objects.push(obj);
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
     pub fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<(f32, HitRecord)> {
+        self.closest_hit(ray, t_min, t_max, Geometry::hit)
+    }
+
+    pub fn hit_surface(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<(f32, HitRecord)> {
+        self.closest_hit(ray, t_min, t_max, Geometry::hit_surface)
+    }
+
+    fn closest_hit(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+        hit: HitFn,
+    ) -> Option<(f32, HitRecord)> {
         let (t, hit_record) =
             self.objects
                 .iter()
                 .fold((t_max, None), |(closest_t, base_hit), obj| {
-                    if let Some((t, rec)) = obj.hit(ray, t_min, closest_t) {
+                    if let Some((t, rec)) = hit(obj, ray, t_min, closest_t) {
                         (t, Some(rec))
                     } else {
                         (closest_t, base_hit)
@@ -86,6 +178,19 @@ impl HittableList {
         hit_record.map(|rec| (t, rec))
     }
 
+    pub fn transmittance(&self, ray: &Ray, t_min: f32, t_max: f32) -> Color {
+        self.objects
+            .iter()
+            .map(|o| o.transmittance(ray, t_min, t_max))
+            .product()
+    }
+
+    pub fn free_flight_pdf(&self, ray: &Ray, t_min: f32, t_max: f32) -> FreeFlightPdf {
+        self.objects.iter().fold(FreeFlightPdf::VACUUM, |pdf, o| {
+            pdf.and(o.free_flight_pdf(ray, t_min, t_max))
+        })
+    }
+
     pub fn bounding_box(&self) -> Option<AABB> {
         if self.objects.is_empty() {
             return None;
@@ -117,12 +222,27 @@ impl HittableList {
     fn random(&self, origin: Point3) -> Vec3A {
         self.objects[rand::random_range(0..self.objects.len())].random(origin)
     }
+
+    fn sample_surface(&self) -> Option<(HitRecord, f32)> {
+        let weight = 1.0 / self.objects.len() as f32;
+        let (rec, pdf) =
+            self.objects[rand::random_range(0..self.objects.len())].sample_surface()?;
+        Some((rec, weight * pdf))
+    }
+
+    fn surface_pdf(&self, p: Point3) -> f32 {
+        let weight = 1.0 / self.objects.len() as f32;
+        self.objects.iter().map(|o| weight * o.surface_pdf(p)).sum()
+    }
 }
 
 pub enum Geometry {
     List(HittableList),
     Sphere(Sphere),
     Branch(Box<BVHBranch>),
+    ConstantMedium(ConstantMedium),
+    HeterogeneousMedium(HeterogeneousMedium),
+    Subsurface(Subsurface),
 }
 
 impl Geometry {
@@ -131,6 +251,46 @@ impl Geometry {
             Geometry::List(l) => l.hit(ray, t_min, t_max),
             Geometry::Sphere(s) => s.hit(ray, t_min, t_max),
             Geometry::Branch(n) => n.hit(ray, t_min, t_max),
+            Geometry::ConstantMedium(m) => m.hit(ray, t_min, t_max),
+            Geometry::HeterogeneousMedium(m) => m.hit(ray, t_min, t_max),
+            Geometry::Subsurface(s) => s.hit(ray, t_min, t_max),
+        }
+    }
+
+    /// Like `hit`, but passing through participating media.
+    pub fn hit_surface(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<(f32, HitRecord)> {
+        match self {
+            Geometry::List(l) => l.hit_surface(ray, t_min, t_max),
+            Geometry::Sphere(s) => s.hit(ray, t_min, t_max),
+            Geometry::Branch(n) => n.hit_surface(ray, t_min, t_max),
+            Geometry::ConstantMedium(_) | Geometry::HeterogeneousMedium(_) => None,
+            Geometry::Subsurface(s) => s.hit_surface(ray, t_min, t_max),
+        }
+    }
+
+    /// The probability of `ray` passing through the participating media
+    /// between `t_min` and `t_max`, ignoring surfaces.
+    pub fn transmittance(&self, ray: &Ray, t_min: f32, t_max: f32) -> Color {
+        match self {
+            Geometry::List(l) => l.transmittance(ray, t_min, t_max),
+            Geometry::Sphere(_) => Color::ONE,
+            Geometry::Branch(n) => n.transmittance(ray, t_min, t_max),
+            Geometry::ConstantMedium(m) => Color::splat(m.transmittance(ray, t_min, t_max)),
+            Geometry::HeterogeneousMedium(m) => Color::splat(m.transmittance(ray, t_min, t_max)),
+            Geometry::Subsurface(s) => s.transmittance(ray, t_min, t_max),
+        }
+    }
+
+    /// The densities of `hit` sampling the distance to a scattering event in
+    /// the participating media, ignoring surfaces.
+    pub fn free_flight_pdf(&self, ray: &Ray, t_min: f32, t_max: f32) -> FreeFlightPdf {
+        match self {
+            Geometry::List(l) => l.free_flight_pdf(ray, t_min, t_max),
+            Geometry::Sphere(_) => FreeFlightPdf::VACUUM,
+            Geometry::Branch(n) => n.free_flight_pdf(ray, t_min, t_max),
+            Geometry::ConstantMedium(m) => m.free_flight_pdf(ray, t_min, t_max),
+            Geometry::HeterogeneousMedium(m) => m.free_flight_pdf(ray, t_min, t_max),
+            Geometry::Subsurface(s) => s.free_flight_pdf(ray, t_min, t_max),
         }
     }
 
@@ -139,6 +299,9 @@ impl Geometry {
             Geometry::List(l) => l.bounding_box(),
             Geometry::Sphere(s) => s.bounding_box(),
             Geometry::Branch(n) => n.bounding_box(),
+            Geometry::ConstantMedium(m) => m.bounding_box(),
+            Geometry::HeterogeneousMedium(m) => m.bounding_box(),
+            Geometry::Subsurface(s) => s.bounding_box(),
         }
     }
 
@@ -157,4 +320,23 @@ impl Geometry {
             _ => Vec3A::new(1.0, 0.0, 0.0),
         }
     }
+
+    /// Samples a point on the surface by area, returning it with its area density.
+    pub fn sample_surface(&self) -> Option<(HitRecord, f32)> {
+        match self {
+            Geometry::List(l) => l.sample_surface(),
+            Geometry::Sphere(s) => Some(s.sample_surface()),
+            _ => None,
+        }
+    }
+
+    /// The area density of `sample_surface` at `p`, which is zero if `p` is
+    /// not on the surface.
+    pub fn surface_pdf(&self, p: Point3) -> f32 {
+        match self {
+            Geometry::List(l) => l.surface_pdf(p),
+            Geometry::Sphere(s) => s.surface_pdf(p),
+            _ => 0.0,
+        }
+    }
 }
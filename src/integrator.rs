@@ -0,0 +1,135 @@
+mod ambient_occlusion;
+mod bdpt;
+mod debug;
+mod direct;
+mod light;
+mod mlt;
+mod path;
+mod spectral;
+mod sppm;
+
+pub use ambient_occlusion::AmbientOcclusionIntegrator;
+pub use bdpt::BDPTIntegrator;
+pub use debug::{DebugIntegrator, DebugMode};
+pub use direct::DirectLightingIntegrator;
+pub use light::LightTracingIntegrator;
+pub use mlt::MLTIntegrator;
+pub use path::PathIntegrator;
+pub use spectral::SpectralIntegrator;
+pub use sppm::SPPMIntegrator;
+
+use glam::Vec3A;
+use indicatif::{ParallelProgressIterator, ProgressBar};
+use rayon::{
+    iter::{IndexedParallelIterator, ParallelIterator},
+    slice::ParallelSliceMut,
+};
+
+use crate::{
+    bsdf::{BSDF, TransportMode},
+    camera::Camera,
+    film::Film,
+    hittable::HitRecord,
+    pdf::power_heuristic,
+    rand,
+    ray::Ray,
+    scene::Scene,
+    vec::{Color, Vec3Ext},
+};
+
+pub trait Integrator: Send + Sync {
+    /// Renders `scene` as seen by `camera` into `film`. `samples_per_pixel`
+    /// is the number of passes for integrators that do not sample pixels
+    /// independently.
+    fn render(&self, scene: &Scene, camera: &Camera, film: &mut Film, samples_per_pixel: u32);
+}
+
+/// An integrator that estimates each camera ray independently.
+pub trait RayIntegrator: Send + Sync {
+    /// Estimates the radiance arriving at the origin of `ray` from its direction.
+    fn li(&self, ray: &Ray, scene: &Scene) -> Color;
+}
+
+impl<T: RayIntegrator> Integrator for T {
+    fn render(&self, scene: &Scene, camera: &Camera, film: &mut Film, samples_per_pixel: u32) {
+        let (width, height) = (film.width(), film.height());
+        let pb = ProgressBar::new(height as u64);
+
+        film.pixels_mut()
+            .par_chunks_mut(width)
+            .enumerate()
+            .progress_with(pb)
+            .for_each(|(row_idx, row_slice)| {
+                let j = height - 1 - row_idx;
+                for (i, pixel) in row_slice.iter_mut().enumerate() {
+                    let mut pixel_color = Color::ZERO;
+                    for _ in 0..samples_per_pixel {
+                        let ray = camera_ray(camera, i, j, width, height);
+                        pixel_color += self.li(&ray, scene);
+                    }
+                    *pixel = pixel_color / samples_per_pixel as f32;
+                }
+            });
+    }
+}
+
+/// A ray through a random position within pixel (`i`, `j`), counting rows
+/// from the bottom of the image.
+fn camera_ray(camera: &Camera, i: usize, j: usize, width: usize, height: usize) -> Ray {
+    let u = (i as f32 + rand::random::<f32>()) / (width - 1) as f32;
+    let v = (j as f32 + rand::random::<f32>()) / (height - 1) as f32;
+    camera.get_ray(u, v)
+}
+
+/// Direct lighting at a non-specular hit, combining a light sample and a
+/// sample of `bsdf` with MIS, for a path arriving from `wo`.
+fn estimate_direct(rec: &HitRecord, bsdf: &BSDF, wo: Vec3A, scene: &Scene) -> Color {
+    let mut color = sample_light(rec, bsdf, wo, scene);
+
+    let Some(sample) = bsdf.sample(wo, Vec3A::random_range(0.0..1.0), TransportMode::Radiance)
+    else {
+        return color;
+    };
+    if sample.pdf > 1e-16 {
+        let le = light_along(&Ray::new(rec.point(), sample.wi), scene);
+        let weight = if sample.flags.is_specular() {
+            1.0
+        } else {
+            power_heuristic(sample.pdf, scene.light_pdf(rec.point(), sample.wi))
+        };
+        color += sample.f * le * weight / sample.pdf;
+    }
+
+    color
+}
+
+/// Light arriving at a non-specular hit through a light sample, MIS
+/// weighted against sampling `bsdf`. Emission found by sampling the BSDF has
+/// to be weighted by the caller.
+fn sample_light(rec: &HitRecord, bsdf: &BSDF, wo: Vec3A, scene: &Scene) -> Color {
+    let Some(direction) = scene.sample_light(rec.point()) else {
+        return Color::ZERO;
+    };
+
+    let shadow = Ray::new(rec.point(), direction);
+    let light_pdf = scene.light_pdf(rec.point(), shadow.direction());
+    let f = bsdf.eval(wo, shadow.direction(), TransportMode::Radiance);
+    if light_pdf < 1e-16 || f == Color::ZERO {
+        return Color::ZERO;
+    }
+
+    let le = light_along(&shadow, scene);
+    let weight = power_heuristic(light_pdf, bsdf.pdf(wo, shadow.direction()));
+    f * le * weight / light_pdf
+}
+
+/// Light emitted towards the origin of `ray` by the first surface it hits or
+/// the background, attenuated by the media in between.
+fn light_along(ray: &Ray, scene: &Scene) -> Color {
+    let (hit, transmittance) = scene.hit_through_media(ray, 0.001, f32::INFINITY);
+    let le = match hit {
+        Some((_, rec)) => rec.material().emitted(rec.u(), rec.v(), rec.point()),
+        None => scene.background().value(ray.direction()),
+    };
+    transmittance * le
+}
@@ -0,0 +1,37 @@
+use glam::Vec3A;
+
+use super::RayIntegrator;
+use crate::{
+    onb::ONB,
+    ray::Ray,
+    scene::Scene,
+    vec::{Color, Vec3Ext},
+};
+
+/// Fraction of the hemisphere above the first hit that is unoccluded within
+/// `distance`, weighted by cosine.
+pub struct AmbientOcclusionIntegrator {
+    distance: f32,
+}
+
+impl AmbientOcclusionIntegrator {
+    pub fn new(distance: f32) -> Self {
+        Self { distance }
+    }
+}
+
+impl RayIntegrator for AmbientOcclusionIntegrator {
+    fn li(&self, ray: &Ray, scene: &Scene) -> Color {
+        let Some((_, rec)) = scene.hit(ray, 0.001, f32::INFINITY) else {
+            return Color::ZERO;
+        };
+
+        let uvw = ONB::build_from_w(rec.normal());
+        let occlusion = Ray::new(rec.point(), uvw.local(Vec3A::random_cosine_direction()));
+        if scene.hit(&occlusion, 0.001, self.distance).is_some() {
+            Color::ZERO
+        } else {
+            Color::ONE
+        }
+    }
+}
@@ -0,0 +1,511 @@
+use std::f32;
+
+use glam::Vec3A;
+
+use super::RayIntegrator;
+use crate::{
+    bsdf::{BSDF, TransportMode},
+    hittable::HitRecord,
+    medium_stack::MediumStack,
+    onb::ONB,
+    pdf::{PDF, power_heuristic},
+    ray::Ray,
+    scene::Scene,
+    vec::{Color, Point3, Vec3Ext},
+};
+
+/// Bidirectional path tracer (Veach 1997) connecting camera and light
+/// subpaths with the power heuristic.
+///
+/// Only the explicit lights of the scene start light subpaths. Light from the
+/// background is gathered by the camera subpath with next-event estimation of
+/// the sun, as in `PathIntegrator`. Strategies that connect a light subpath
+/// straight to the camera (t = 1) are not used, so every estimate stays in
+/// its own pixel.
+pub struct BDPTIntegrator {
+    max_depth: usize,
+}
+
+impl BDPTIntegrator {
+    pub fn new(max_depth: usize) -> Self {
+        Self { max_depth }
+    }
+}
+
+enum Interaction {
+    Camera,
+    /// A point sampled on the surface of a light, with its outward normal.
+    Light(HitRecord),
+    Surface {
+        rec: HitRecord,
+        /// The direction back towards the previous vertex.
+        wo: Vec3A,
+        /// `None` where the path ends without scattering.
+        bsdf: Option<BSDF>,
+        /// Whether the subpath carrying this vertex started at the camera.
+        mode: TransportMode,
+    },
+}
+
+struct Vertex {
+    interaction: Interaction,
+    p: Point3,
+    /// Throughput of the subpath up to and excluding this vertex's scattering.
+    beta: Color,
+    /// Area density of sampling this vertex from the previous one.
+    pdf_fwd: f32,
+    /// Area density of sampling this vertex from the next one, i.e. when the
+    /// path is generated from the other end.
+    pdf_rev: f32,
+    delta: bool,
+}
+
+impl Vertex {
+    fn camera(p: Point3) -> Self {
+        Self {
+            interaction: Interaction::Camera,
+            p,
+            beta: Color::ONE,
+            pdf_fwd: 0.0,
+            pdf_rev: 0.0,
+            delta: false,
+        }
+    }
+
+    fn light(rec: HitRecord, pdf_area: f32) -> Self {
+        Self {
+            p: rec.point(),
+            interaction: Interaction::Light(rec),
+            beta: Color::splat(1.0 / pdf_area),
+            pdf_fwd: pdf_area,
+            pdf_rev: 0.0,
+            delta: false,
+        }
+    }
+
+    fn normal(&self) -> Option<Vec3A> {
+        match &self.interaction {
+            Interaction::Camera => None,
+            _ if self.is_medium() => None,
+            Interaction::Light(rec) | Interaction::Surface { rec, .. } => Some(rec.normal()),
+        }
+    }
+
+    fn is_medium(&self) -> bool {
+        matches!(&self.interaction, Interaction::Surface { rec, .. } if rec.material().is_medium())
+    }
+
+    fn is_connectible(&self) -> bool {
+        match &self.interaction {
+            Interaction::Camera => false,
+            Interaction::Light(_) => true,
+            Interaction::Surface { bsdf, .. } => bsdf
+                .as_ref()
+                .is_some_and(|bsdf| bsdf.flags().is_non_specular()),
+        }
+    }
+
+    /// The scattering function times the cosine at this vertex for light
+    /// leaving towards `direction`. For lights this is the emitted radiance.
+    fn f_cos(&self, direction: Vec3A) -> Color {
+        match &self.interaction {
+            Interaction::Camera => Color::ZERO,
+            Interaction::Light(rec) => {
+                let cosine = rec.normal().dot(direction);
+                if cosine <= 0.0 {
+                    return Color::ZERO;
+                }
+                cosine * rec.material().emitted(rec.u(), rec.v(), rec.point())
+            }
+            Interaction::Surface {
+                wo,
+                bsdf: Some(bsdf),
+                mode,
+                ..
+            } => bsdf.eval(*wo, direction, *mode),
+            Interaction::Surface { bsdf: None, .. } => Color::ZERO,
+        }
+    }
+
+    /// Solid angle density of sampling `direction` when leaving this vertex.
+    /// Scattering depends on the direction the path arrived in, which
+    /// `incoming` overrides when the path is evaluated in reverse.
+    fn pdf_dir(&self, incoming: Option<Vec3A>, direction: Vec3A) -> f32 {
+        match &self.interaction {
+            Interaction::Camera => 0.0,
+            Interaction::Light(rec) => rec.normal().dot(direction).max(0.0) / f32::consts::PI,
+            Interaction::Surface {
+                wo,
+                bsdf: Some(bsdf),
+                ..
+            } => bsdf.pdf(incoming.map_or(*wo, |incoming| -incoming), direction),
+            Interaction::Surface { bsdf: None, .. } => 0.0,
+        }
+    }
+
+    /// Area density of sampling `next` when leaving this vertex, having
+    /// arrived from `prev` if it differs from the path's own previous vertex.
+    fn pdf_area(&self, scene: &Scene, prev: Option<&Vertex>, next: &Vertex) -> f32 {
+        let incoming = prev.map(|prev| (self.p - prev.p).normalize());
+        let direction = (next.p - self.p).normalize();
+        convert_density(scene, self.pdf_dir(incoming, direction), self.p, next)
+    }
+
+    /// Area density of a light subpath leaving this point of an emitter
+    /// towards `next`.
+    fn pdf_emission(&self, scene: &Scene, next: &Vertex) -> f32 {
+        let Some(normal) = self.normal() else {
+            return 0.0;
+        };
+        let direction = (next.p - self.p).normalize();
+        let pdf_dir = normal.dot(direction).max(0.0) / f32::consts::PI;
+        convert_density(scene, pdf_dir, self.p, next)
+    }
+
+    fn emitted(&self) -> Color {
+        match &self.interaction {
+            Interaction::Surface { rec, .. } if rec.front_face() => {
+                rec.material().emitted(rec.u(), rec.v(), rec.point())
+            }
+            _ => Color::ZERO,
+        }
+    }
+}
+
+/// Converts a solid angle density at `from` to an area density at `to`, or
+/// a volume density where `to` is inside a medium, including the density of
+/// the media sampling the distance to it.
+fn convert_density(scene: &Scene, pdf_dir: f32, from: Point3, to: &Vertex) -> f32 {
+    let w = to.p - from;
+    let distance_squared = w.length_squared();
+    if distance_squared == 0.0 {
+        return 0.0;
+    }
+    let cosine = to
+        .normal()
+        .map_or(1.0, |n| n.dot(w).abs() / distance_squared.sqrt());
+    let free_flight = scene.free_flight_pdf(from, to.p);
+    let pdf_distance = if to.is_medium() {
+        free_flight.scatter
+    } else {
+        free_flight.pass
+    };
+    pdf_dir * cosine * pdf_distance / distance_squared
+}
+
+fn remap0(pdf: f32) -> f32 {
+    if pdf == 0.0 { 1.0 } else { pdf }
+}
+
+/// The ray a camera subpath escaped the scene with.
+struct Escape {
+    direction: Vec3A,
+    beta: Color,
+    /// Solid angle density the ray was sampled with, `None` if it was not
+    /// sampled from a diffuse surface.
+    pdf_dir: Option<f32>,
+}
+
+/// Extends `path` by following `ray` until it holds `max_vertices`, the
+/// ray escapes or a surface does not scatter.
+fn random_walk(
+    scene: &Scene,
+    mut ray: Ray,
+    mut beta: Color,
+    mut pdf_dir: Option<f32>,
+    max_vertices: usize,
+    mode: TransportMode,
+    path: &mut Vec<Vertex>,
+) -> Option<Escape> {
+    let mut media = MediumStack::new();
+    while path.len() < max_vertices {
+        let Some((rec, transmittance)) = scene.hit_nested(&ray, &mut media) else {
+            return Some(Escape {
+                direction: ray.direction(),
+                beta,
+                pdf_dir,
+            });
+        };
+        beta *= transmittance;
+
+        let prev = path.len() - 1;
+        let bsdf = if path.len() + 1 < max_vertices {
+            rec.material().bsdf(&rec)
+        } else {
+            None
+        };
+        let sample = bsdf
+            .as_ref()
+            .and_then(|bsdf| bsdf.sample(-ray.direction(), Vec3A::random_range(0.0..1.0), mode));
+
+        let mut vertex = Vertex {
+            p: rec.point(),
+            interaction: Interaction::Surface {
+                rec,
+                wo: -ray.direction(),
+                bsdf,
+                mode,
+            },
+            beta,
+            pdf_fwd: 0.0,
+            pdf_rev: 0.0,
+            delta: false,
+        };
+        vertex.pdf_fwd = convert_density(scene, pdf_dir.unwrap_or(0.0), path[prev].p, &vertex);
+
+        let Some(sample) = sample else {
+            path.push(vertex);
+            break;
+        };
+
+        if sample.flags.is_specular() {
+            if let Interaction::Surface { rec, .. } = &vertex.interaction {
+                media.cross(rec, ray.direction(), sample.wi);
+            }
+            vertex.delta = true;
+            pdf_dir = None;
+        } else {
+            let pdf_rev = vertex.pdf_dir(Some(-sample.wi), -ray.direction());
+            path[prev].pdf_rev = convert_density(scene, pdf_rev, vertex.p, &path[prev]);
+            pdf_dir = Some(sample.pdf);
+        }
+        path.push(vertex);
+
+        if sample.pdf < 1e-16 {
+            break;
+        }
+        beta *= sample.f / sample.pdf;
+        ray = Ray::new(path[path.len() - 1].p, sample.wi);
+    }
+
+    None
+}
+
+impl BDPTIntegrator {
+    fn light_subpath(&self, scene: &Scene) -> Vec<Vertex> {
+        let mut path = Vec::with_capacity(self.max_depth + 1);
+        let Some((rec, pdf_area)) = scene.sample_light_surface() else {
+            return path;
+        };
+
+        let direction = ONB::build_from_w(rec.normal()).local(Vec3A::random_cosine_direction());
+        let origin = Vertex::light(rec, pdf_area);
+        let pdf_dir = origin.pdf_dir(None, direction);
+        if pdf_dir < 1e-16 {
+            return path;
+        }
+
+        let beta = origin.beta * origin.f_cos(direction) / pdf_dir;
+        let ray = Ray::new(origin.p, direction);
+        path.push(origin);
+        random_walk(
+            scene,
+            ray,
+            beta,
+            Some(pdf_dir),
+            self.max_depth + 1,
+            TransportMode::Importance,
+            &mut path,
+        );
+        path
+    }
+
+    /// Light reaching the camera subpath from the background, with the
+    /// escaped ray MIS weighted against sampling the sun at each vertex.
+    fn background(&self, scene: &Scene, camera: &[Vertex], escape: Option<Escape>) -> Color {
+        let sun = scene.background().sun_pdf();
+        let mut color = Color::ZERO;
+
+        if let Some(escape) = escape {
+            let weight = match (&sun, escape.pdf_dir) {
+                (Some(sun), Some(pdf_dir)) => power_heuristic(pdf_dir, sun.value(escape.direction)),
+                _ => 1.0,
+            };
+            color += escape.beta * scene.background().value(escape.direction) * weight;
+        }
+
+        let Some(sun) = sun else {
+            return color;
+        };
+        for vertex in camera.iter().take(self.max_depth + 1).skip(1) {
+            if !vertex.is_connectible() {
+                continue;
+            }
+            let shadow = Ray::new(vertex.p, sun.generate());
+            let sun_pdf = sun.value(shadow.direction());
+            let f_cos = vertex.f_cos(shadow.direction());
+            if sun_pdf < 1e-16 || f_cos == Color::ZERO {
+                continue;
+            }
+            let (hit, transmittance) = scene.hit_through_media(&shadow, 0.001, f32::INFINITY);
+            if hit.is_some() {
+                continue;
+            }
+            let weight = power_heuristic(sun_pdf, vertex.pdf_dir(None, shadow.direction()));
+            color += vertex.beta
+                * f_cos
+                * transmittance
+                * scene.background().value(shadow.direction())
+                * weight
+                / sun_pdf;
+        }
+
+        color
+    }
+
+    /// The unweighted contribution of the strategy using `s` light and `t`
+    /// camera vertices, with the light vertex sampled for `s == 1`.
+    fn connect(
+        &self,
+        scene: &Scene,
+        light: &[Vertex],
+        camera: &[Vertex],
+        s: usize,
+        t: usize,
+    ) -> Color {
+        let pt = &camera[t - 1];
+
+        if s == 0 {
+            let emitted = pt.emitted();
+            if emitted == Color::ZERO {
+                return Color::ZERO;
+            }
+            return pt.beta * emitted * mis_weight(scene, light, camera, None, s, t);
+        }
+
+        if !pt.is_connectible() {
+            return Color::ZERO;
+        }
+
+        let sampled = if s == 1 {
+            let Some((rec, pdf_area)) = scene.sample_light_surface() else {
+                return Color::ZERO;
+            };
+            Some(Vertex::light(rec, pdf_area))
+        } else {
+            None
+        };
+        let qs = sampled.as_ref().unwrap_or(&light[s - 1]);
+        if !qs.is_connectible() {
+            return Color::ZERO;
+        }
+
+        let w = pt.p - qs.p;
+        let distance = w.length();
+        let direction = w / distance;
+        let contribution =
+            qs.beta * qs.f_cos(direction) * pt.f_cos(-direction) * pt.beta / (distance * distance);
+        if contribution == Color::ZERO {
+            return Color::ZERO;
+        }
+
+        let shadow = Ray::new(pt.p, -direction);
+        let (hit, transmittance) = scene.hit_through_media(&shadow, 0.001, distance - 0.001);
+        if hit.is_some() || transmittance == Color::ZERO {
+            return Color::ZERO;
+        }
+
+        contribution * transmittance * mis_weight(scene, light, camera, sampled.as_ref(), s, t)
+    }
+}
+
+/// Power heuristic weight of strategy (s, t) among all strategies able to
+/// produce the same path, computed from ratios of the vertex densities.
+fn mis_weight(
+    scene: &Scene,
+    light: &[Vertex],
+    camera: &[Vertex],
+    sampled: Option<&Vertex>,
+    s: usize,
+    t: usize,
+) -> f32 {
+    if s + t == 2 {
+        return 1.0;
+    }
+
+    let pt = &camera[t - 1];
+    let pt_minus = &camera[t - 2];
+    let qs = sampled.or_else(|| s.checked_sub(1).map(|i| &light[i]));
+    let qs_minus = s.checked_sub(2).map(|i| &light[i]);
+
+    // (pdf_fwd, pdf_rev, delta) along both subpaths, updated for the connection.
+    let densities = |v: &Vertex| (v.pdf_fwd, v.pdf_rev, v.delta);
+    let mut cam: Vec<_> = camera[..t].iter().map(densities).collect();
+    let mut lig: Vec<_> = light[..s.min(light.len())].iter().map(densities).collect();
+    if let Some(sampled) = sampled {
+        lig.truncate(0);
+        lig.push(densities(sampled));
+    }
+
+    match qs {
+        Some(qs) => {
+            cam[t - 1].1 = qs.pdf_area(scene, None, pt);
+            cam[t - 2].1 = pt.pdf_area(scene, Some(qs), pt_minus);
+            lig[s - 1].1 = pt.pdf_area(scene, None, qs);
+            lig[s - 1].2 = false;
+            if let Some(qs_minus) = qs_minus {
+                lig[s - 2].1 = qs.pdf_area(scene, Some(pt), qs_minus);
+            }
+        }
+        None => {
+            let pdf_origin = scene.light_surface_pdf(pt.p);
+            if pdf_origin == 0.0 {
+                // Not one of the explicit lights, so no other strategy exists.
+                return 1.0;
+            }
+            cam[t - 1].1 = pdf_origin;
+            cam[t - 2].1 = pt.pdf_emission(scene, pt_minus);
+        }
+    }
+    cam[t - 1].2 = false;
+
+    let mut sum = 0.0;
+
+    let mut ri = 1.0;
+    for i in (2..t).rev() {
+        ri *= remap0(cam[i].1) / remap0(cam[i].0);
+        if !cam[i].2 && !cam[i - 1].2 {
+            sum += ri * ri;
+        }
+    }
+
+    let mut ri = 1.0;
+    for i in (0..s).rev() {
+        ri *= remap0(lig[i].1) / remap0(lig[i].0);
+        let delta_prev = i > 0 && lig[i - 1].2;
+        if !lig[i].2 && !delta_prev {
+            sum += ri * ri;
+        }
+    }
+
+    1.0 / (1.0 + sum)
+}
+
+impl RayIntegrator for BDPTIntegrator {
+    fn li(&self, ray: &Ray, scene: &Scene) -> Color {
+        let mut camera = Vec::with_capacity(self.max_depth + 2);
+        camera.push(Vertex::camera(*ray.origin()));
+        let escape = random_walk(
+            scene,
+            *ray,
+            Color::ONE,
+            None,
+            self.max_depth + 2,
+            TransportMode::Radiance,
+            &mut camera,
+        );
+        let light = self.light_subpath(scene);
+
+        let mut color = self.background(scene, &camera, escape);
+        for t in 2..=camera.len() {
+            for s in 0..=light.len().max(1) {
+                if s + t - 2 > self.max_depth {
+                    continue;
+                }
+                color += self.connect(scene, &light, &camera, s, t);
+            }
+        }
+
+        color
+    }
+}
@@ -0,0 +1,38 @@
+use super::RayIntegrator;
+use crate::{ray::Ray, scene::Scene, vec::Color};
+
+#[derive(Clone, Copy)]
+pub enum DebugMode {
+    /// Shading normal at the first hit, mapped from [-1, 1] to [0, 1].
+    Normals,
+    /// Color of the material at the first hit, see `BxDF::albedo`, or its
+    /// emission.
+    Albedo,
+}
+
+/// Visualizes properties of the first surface hit instead of lighting.
+pub struct DebugIntegrator {
+    mode: DebugMode,
+}
+
+impl DebugIntegrator {
+    pub fn new(mode: DebugMode) -> Self {
+        Self { mode }
+    }
+}
+
+impl RayIntegrator for DebugIntegrator {
+    fn li(&self, ray: &Ray, scene: &Scene) -> Color {
+        let Some((_, rec)) = scene.hit(ray, 0.001, f32::INFINITY) else {
+            return Color::ZERO;
+        };
+
+        match self.mode {
+            DebugMode::Normals => 0.5 * (rec.normal() + Color::ONE),
+            DebugMode::Albedo => match rec.material().bsdf(&rec) {
+                Some(bsdf) => bsdf.bxdf().albedo(),
+                None => rec.material().emitted(rec.u(), rec.v(), rec.point()),
+            },
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use super::{RayIntegrator, estimate_direct};
+use glam::Vec3A;
+
+use crate::{
+    bsdf::TransportMode,
+    medium_stack::MediumStack,
+    ray::Ray,
+    scene::Scene,
+    vec::{Color, Vec3Ext},
+};
+
+/// Direct lighting only: light reaching the first diffuse surface straight
+/// from an emitter, following perfectly specular bounces up to `max_depth`.
+pub struct DirectLightingIntegrator {
+    max_depth: i32,
+}
+
+impl DirectLightingIntegrator {
+    pub fn new(max_depth: i32) -> Self {
+        Self { max_depth }
+    }
+}
+
+impl RayIntegrator for DirectLightingIntegrator {
+    fn li(&self, ray: &Ray, scene: &Scene) -> Color {
+        let mut ray = *ray;
+        let mut throughput = Color::ONE;
+        let mut media = MediumStack::new();
+
+        for _ in 0..self.max_depth {
+            let Some((rec, transmittance)) = scene.hit_nested(&ray, &mut media) else {
+                return throughput * scene.background().value(ray.direction());
+            };
+            throughput *= transmittance;
+
+            let emitted = rec.material().emitted(rec.u(), rec.v(), rec.point());
+            let Some(bsdf) = rec.material().bsdf(&rec) else {
+                return throughput * emitted;
+            };
+            let wo = -ray.direction();
+
+            if bsdf.flags().is_non_specular() {
+                let color = emitted + estimate_direct(&rec, &bsdf, wo, scene);
+                return throughput * color;
+            }
+
+            let Some(sample) =
+                bsdf.sample(wo, Vec3A::random_range(0.0..1.0), TransportMode::Radiance)
+            else {
+                return throughput * emitted;
+            };
+            media.cross(&rec, ray.direction(), sample.wi);
+            throughput *= sample.f / sample.pdf;
+            ray = Ray::new(rec.point(), sample.wi);
+        }
+
+        Color::ZERO
+    }
+}
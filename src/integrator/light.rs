@@ -0,0 +1,194 @@
+use std::f32;
+
+use glam::Vec3A;
+use indicatif::{ParallelProgressIterator, ProgressBar};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+use super::Integrator;
+use crate::{
+    bsdf::TransportMode,
+    camera::{Camera, Projection},
+    film::{Film, SplatFilm},
+    medium_stack::MediumStack,
+    onb::ONB,
+    ray::Ray,
+    scene::Scene,
+    vec::{Color, Point3, Vec3Ext},
+};
+
+/// Light tracer: follows paths from the explicit lights and connects every
+/// vertex to the camera, splatting the contribution into the pixel it is
+/// seen in.
+///
+/// Specular surfaces cannot be connected to the camera, so objects are only
+/// seen through their diffuse reflection, and light from the background is
+/// not traced at all. `samples_per_pixel` light paths are traced per pixel of
+/// the film.
+pub struct LightTracingIntegrator {
+    max_depth: i32,
+}
+
+impl LightTracingIntegrator {
+    pub fn new(max_depth: i32) -> Self {
+        Self { max_depth }
+    }
+
+    fn trace(&self, scene: &Scene, camera: &Camera, film: &SplatFilm) {
+        let Some((light, pdf_area)) = scene.sample_light_surface() else {
+            return;
+        };
+
+        // Lights seen directly by the camera.
+        let emitted = light
+            .material()
+            .emitted(light.u(), light.v(), light.point());
+        connect(scene, camera, film, light.point(), |direction| {
+            emitted * light.normal().dot(direction).max(0.0) / pdf_area
+        });
+
+        let direction = ONB::build_from_w(light.normal()).local(Vec3A::random_cosine_direction());
+        let cosine = light.normal().dot(direction);
+        let pdf_dir = cosine / f32::consts::PI;
+        if pdf_dir < 1e-16 {
+            return;
+        }
+
+        let mut beta = emitted * cosine / (pdf_area * pdf_dir);
+        let mut ray = Ray::new(light.point(), direction);
+        let mut media = MediumStack::new();
+
+        for _ in 0..self.max_depth {
+            let Some((rec, transmittance)) = scene.hit_nested(&ray, &mut media) else {
+                break;
+            };
+            beta *= transmittance;
+            let Some(bsdf) = rec.material().bsdf(&rec) else {
+                break;
+            };
+            let wo = -ray.direction();
+
+            if bsdf.flags().is_non_specular() {
+                connect(scene, camera, film, rec.point(), |direction| {
+                    beta * bsdf.eval(wo, direction, TransportMode::Importance)
+                });
+            }
+
+            let Some(sample) =
+                bsdf.sample(wo, Vec3A::random_range(0.0..1.0), TransportMode::Importance)
+            else {
+                break;
+            };
+            if sample.pdf < 1e-16 {
+                break;
+            }
+            if sample.flags.is_specular() {
+                media.cross(&rec, ray.direction(), sample.wi);
+            }
+            beta *= sample.f / sample.pdf;
+            ray = Ray::new(rec.point(), sample.wi);
+        }
+    }
+}
+
+/// Splats the light leaving `p` towards the camera, where `contribution`
+/// gives the path throughput times the scattering function and the cosine
+/// at `p` for a direction.
+fn connect(
+    scene: &Scene,
+    camera: &Camera,
+    film: &SplatFilm,
+    p: Point3,
+    contribution: impl FnOnce(Vec3A) -> Color,
+) {
+    let Some(projection) = camera.project(p) else {
+        return;
+    };
+    let Some((i, j)) = pixel(&projection, film.width(), film.height()) else {
+        return;
+    };
+
+    let to_camera = projection.lens_point - p;
+    let distance = to_camera.length();
+    let shadow = Ray::new(p, to_camera);
+    let (hit, transmittance) = scene.hit_through_media(&shadow, 0.001, distance * (1.0 - 1e-4));
+    if hit.is_some() || transmittance == Color::ZERO {
+        return;
+    }
+
+    let color = contribution(shadow.direction()) * transmittance * projection.weight;
+    if color.is_finite() {
+        film.splat(i, j, color);
+    }
+}
+
+/// The pixel a projection lands in, with rows counted from the bottom as in
+/// `camera_ray`.
+fn pixel(projection: &Projection, width: usize, height: usize) -> Option<(usize, usize)> {
+    let i = (projection.s * (width - 1) as f32).floor();
+    let j = (projection.t * (height - 1) as f32).floor();
+    if i < 0.0 || j < 0.0 || i >= width as f32 || j >= height as f32 {
+        return None;
+    }
+    Some((i as usize, j as usize))
+}
+
+impl Integrator for LightTracingIntegrator {
+    fn render(&self, scene: &Scene, camera: &Camera, film: &mut Film, samples_per_pixel: u32) {
+        let (width, height) = (film.width(), film.height());
+        let splats = SplatFilm::new(width, height);
+
+        // Each task traces one light path per pixel of a row.
+        let tasks = samples_per_pixel as u64 * height as u64;
+        let pb = ProgressBar::new(tasks);
+        (0..tasks).into_par_iter().progress_with(pb).for_each(|_| {
+            for _ in 0..width {
+                self.trace(scene, camera, &splats);
+            }
+        });
+
+        // The weights of `Camera::project` integrate to one over the unit
+        // square of image coordinates, in which a pixel spans an area of
+        // 1 / ((width - 1) * (height - 1)).
+        let paths = tasks as f32 * width as f32;
+        splats.resolve(film, ((width - 1) * (height - 1)) as f32 / paths);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{integrator::camera_ray, rand};
+
+    fn camera() -> Camera {
+        Camera::new(Point3::ZERO, -Vec3A::Z, Vec3A::Y, 60.0, 1.5, 0.0, 1.0)
+    }
+
+    #[test]
+    fn forward_axis_lands_on_center_pixel() {
+        let projection = camera().project(Point3::new(0.0, 0.0, -2.0)).unwrap();
+        assert_eq!(pixel(&projection, 7, 5), Some((3, 2)));
+    }
+
+    #[test]
+    fn pixels_match_camera_rays() {
+        // The splat scale assumes that a pixel covers the image coordinates
+        // of the camera rays sampled for it.
+        let camera = camera();
+        let (width, height) = (8, 6);
+        for _ in 0..100 {
+            let (i, j) = (
+                rand::random_range(0..width),
+                rand::random_range(0..height - 1),
+            );
+            let ray = camera_ray(&camera, i, j, width, height);
+            let projection = camera.project(ray.at(2.0)).unwrap();
+            let inside = |s: f32, n: usize, k: usize| {
+                let x = s * (n - 1) as f32;
+                x > k as f32 + 1e-3 && x < (k + 1) as f32 - 1e-3
+            };
+            if inside(projection.s, width, i) && inside(projection.t, height, j) {
+                assert_eq!(pixel(&projection, width, height), Some((i, j)));
+            }
+        }
+    }
+}
@@ -0,0 +1,341 @@
+use std::{cell::RefCell, f32, rc::Rc};
+
+use ::rand::{Rng, RngCore, SeedableRng, rngs::SmallRng};
+use indicatif::{ParallelProgressIterator, ProgressBar};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+use super::{Integrator, PathIntegrator, RayIntegrator};
+use crate::{camera::Camera, color, film::Film, rand, scene::Scene, vec::Color};
+
+/// Primary sample space Metropolis light transport (Kelemen et al. 2002).
+///
+/// Markov chains mutate the uniform random numbers consumed by
+/// `PathIntegrator`, mixing independent large steps with small gaussian
+/// perturbations, and splat both the proposed and the current sample with
+/// their expected weights. The brightness of the image is recovered from an
+/// initial set of bootstrap samples.
+pub struct MLTIntegrator {
+    path: PathIntegrator,
+    bootstrap_samples: usize,
+    chains: usize,
+    /// Standard deviation of small step mutations.
+    sigma: f32,
+    large_step_probability: f32,
+}
+
+impl MLTIntegrator {
+    pub fn new(
+        max_depth: i32,
+        bootstrap_samples: usize,
+        chains: usize,
+        sigma: f32,
+        large_step_probability: f32,
+    ) -> Self {
+        Self {
+            // Russian roulette would make small mutations change the length
+            // of the path abruptly.
+            path: PathIntegrator::new(max_depth, max_depth),
+            bootstrap_samples,
+            chains,
+            sigma,
+            large_step_probability,
+        }
+    }
+
+    /// Evaluates the path driven by the current primary samples of
+    /// `sampler`, returning its radiance and the index of its pixel.
+    fn evaluate(&self, target: &Target, sampler: &Rc<RefCell<MLTSampler>>) -> (Color, usize) {
+        let Target {
+            scene,
+            camera,
+            width,
+            height,
+        } = *target;
+        rand::with_rng(SharedSampler(sampler.clone()), || {
+            let x = rand::random::<f32>() * width as f32;
+            let y = rand::random::<f32>() * height as f32;
+            let i = (x as usize).min(width - 1);
+            let j = (y as usize).min(height - 1);
+
+            let ray = camera.get_ray(x / (width - 1) as f32, y / (height - 1) as f32);
+            let color = self.path.li(&ray, scene);
+            let color = if color.is_finite() {
+                color
+            } else {
+                Color::ZERO
+            };
+            (color, (height - 1 - j) * width + i)
+        })
+    }
+
+    fn sampler(&self, seed: u64) -> Rc<RefCell<MLTSampler>> {
+        Rc::new(RefCell::new(MLTSampler::new(
+            seed,
+            self.sigma,
+            self.large_step_probability,
+        )))
+    }
+
+    /// Runs a chain from the bootstrap sample with sampler seed `seed`,
+    /// adding its splats to `pixels`.
+    fn run_chain(
+        &self,
+        target: &Target,
+        seed: u64,
+        mutations: u64,
+        rng: &mut SmallRng,
+        pixels: &mut [Color],
+    ) {
+        let sampler = self.sampler(seed);
+
+        let (mut current, mut current_pixel) = self.evaluate(target, &sampler);
+        for _ in 0..mutations {
+            sampler.borrow_mut().start_iteration();
+            let (proposed, proposed_pixel) = self.evaluate(target, &sampler);
+
+            let current_y = color::luminance(current);
+            let proposed_y = color::luminance(proposed);
+            let accept = if current_y > 0.0 {
+                (proposed_y / current_y).min(1.0)
+            } else {
+                1.0
+            };
+
+            if proposed_y > 0.0 {
+                pixels[proposed_pixel] += proposed * accept / proposed_y;
+            }
+            if current_y > 0.0 {
+                pixels[current_pixel] += current * (1.0 - accept) / current_y;
+            }
+
+            if rng.random::<f32>() < accept {
+                current = proposed;
+                current_pixel = proposed_pixel;
+                sampler.borrow_mut().accept();
+            } else {
+                sampler.borrow_mut().reject();
+            }
+        }
+    }
+}
+
+impl Integrator for MLTIntegrator {
+    fn render(&self, scene: &Scene, camera: &Camera, film: &mut Film, samples_per_pixel: u32) {
+        let target = Target {
+            scene,
+            camera,
+            width: film.width(),
+            height: film.height(),
+        };
+        let pixel_count = target.width * target.height;
+        let base_seed: u64 = rand::random();
+
+        let weights: Vec<f32> = (0..self.bootstrap_samples)
+            .into_par_iter()
+            .map(|index| {
+                let sampler = self.sampler(base_seed.wrapping_add(index as u64));
+                color::luminance(self.evaluate(&target, &sampler).0)
+            })
+            .collect();
+        let total: f32 = weights.iter().sum();
+        if total <= 0.0 {
+            film.pixels_mut().fill(Color::ZERO);
+            return;
+        }
+        let b = total / self.bootstrap_samples as f32;
+
+        // Chains start from bootstrap samples chosen proportionally to their
+        // luminance, so that they are already distributed like the image.
+        let cdf: Vec<f32> = weights
+            .iter()
+            .scan(0.0, |sum, w| {
+                *sum += w / total;
+                Some(*sum)
+            })
+            .collect();
+
+        let mutations = samples_per_pixel as u64 * pixel_count as u64;
+        // Every chain mutates at least once, so tiny images still average
+        // over a nonzero number of samples.
+        let mutations_per_chain = (mutations / self.chains as u64).max(1);
+
+        let pb = ProgressBar::new(self.chains as u64);
+        let pixels = (0..self.chains)
+            .into_par_iter()
+            .progress_with(pb)
+            .fold(
+                || vec![Color::ZERO; pixel_count],
+                |mut pixels, chain| {
+                    let mut rng = SmallRng::seed_from_u64(
+                        base_seed.wrapping_add((self.bootstrap_samples + chain) as u64),
+                    );
+                    let u = rng.random::<f32>();
+                    let index = cdf.partition_point(|&c| c < u).min(weights.len() - 1);
+                    self.run_chain(
+                        &target,
+                        base_seed.wrapping_add(index as u64),
+                        mutations_per_chain,
+                        &mut rng,
+                        &mut pixels,
+                    );
+                    pixels
+                },
+            )
+            .reduce(
+                || vec![Color::ZERO; pixel_count],
+                |mut a, b| {
+                    a.iter_mut().zip(b).for_each(|(a, b)| *a += b);
+                    a
+                },
+            );
+
+        let scale = b * pixel_count as f32 / (mutations_per_chain * self.chains as u64) as f32;
+        for (color, splat) in film.pixels_mut().iter_mut().zip(pixels) {
+            *color = splat * scale;
+        }
+    }
+}
+
+/// What the chains render into.
+#[derive(Clone, Copy)]
+struct Target<'a> {
+    scene: &'a Scene,
+    camera: &'a Camera,
+    width: usize,
+    height: usize,
+}
+
+#[derive(Clone, Copy)]
+struct PrimarySample {
+    value: f32,
+    /// Iteration in which `value` was last changed.
+    last_modification: u64,
+    value_backup: f32,
+    modify_backup: u64,
+}
+
+impl PrimarySample {
+    fn backup(&mut self) {
+        self.value_backup = self.value;
+        self.modify_backup = self.last_modification;
+    }
+
+    fn restore(&mut self) {
+        self.value = self.value_backup;
+        self.last_modification = self.modify_backup;
+    }
+}
+
+/// A vector of primary samples in [0, 1) that is lazily mutated as the
+/// samples are consumed, following pbrt's `MLTSampler`.
+struct MLTSampler {
+    rng: SmallRng,
+    sigma: f32,
+    large_step_probability: f32,
+    x: Vec<PrimarySample>,
+    current_iteration: u64,
+    large_step: bool,
+    last_large_step_iteration: u64,
+    sample_index: usize,
+}
+
+impl MLTSampler {
+    fn new(seed: u64, sigma: f32, large_step_probability: f32) -> Self {
+        Self {
+            rng: SmallRng::seed_from_u64(seed),
+            sigma,
+            large_step_probability,
+            x: vec![],
+            current_iteration: 0,
+            large_step: true,
+            last_large_step_iteration: 0,
+            sample_index: 0,
+        }
+    }
+
+    fn start_iteration(&mut self) {
+        self.current_iteration += 1;
+        self.large_step = self.rng.random::<f32>() < self.large_step_probability;
+        self.sample_index = 0;
+    }
+
+    fn accept(&mut self) {
+        if self.large_step {
+            self.last_large_step_iteration = self.current_iteration;
+        }
+    }
+
+    fn reject(&mut self) {
+        for xi in &mut self.x {
+            if xi.last_modification == self.current_iteration {
+                xi.restore();
+            }
+        }
+        self.current_iteration -= 1;
+    }
+
+    fn next_sample(&mut self) -> f32 {
+        let index = self.sample_index;
+        self.sample_index += 1;
+        if index >= self.x.len() {
+            // Samples first used in a small step have no previous value to
+            // perturb, so they start out uniformly distributed.
+            let value = self.rng.random();
+            self.x.push(PrimarySample {
+                value,
+                last_modification: self.last_large_step_iteration,
+                value_backup: value,
+                modify_backup: self.last_large_step_iteration,
+            });
+        }
+
+        let xi = &mut self.x[index];
+        // Samples not touched since the last accepted large step are stale
+        // and have to be regenerated first.
+        if xi.last_modification < self.last_large_step_iteration {
+            xi.value = self.rng.random();
+            xi.last_modification = self.last_large_step_iteration;
+        }
+
+        xi.backup();
+        if self.large_step {
+            xi.value = self.rng.random();
+        } else {
+            // Small steps missed since the last modification are applied at once.
+            let small_steps = (self.current_iteration - xi.last_modification) as f32;
+            let sigma = self.sigma * small_steps.sqrt();
+            xi.value += sigma * standard_normal(&mut self.rng);
+            xi.value -= xi.value.floor();
+        }
+        xi.last_modification = self.current_iteration;
+
+        xi.value.min(1.0 - f32::EPSILON / 2.0)
+    }
+}
+
+fn standard_normal(rng: &mut SmallRng) -> f32 {
+    let u1: f32 = 1.0 - rng.random::<f32>();
+    let u2: f32 = rng.random();
+    f32::sqrt(-2.0 * u1.ln()) * f32::cos(2.0 * f32::consts::PI * u2)
+}
+
+/// Feeds the primary samples of an `MLTSampler` to `rand` as raw integers,
+/// so that uniform floats drawn from them map back to the primary samples.
+struct SharedSampler(Rc<RefCell<MLTSampler>>);
+
+impl RngCore for SharedSampler {
+    fn next_u32(&mut self) -> u32 {
+        (self.0.borrow_mut().next_sample() as f64 * 4294967296.0) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        (self.0.borrow_mut().next_sample() as f64 * 18446744073709551616.0) as u64
+    }
+
+    fn fill_bytes(&mut self, dst: &mut [u8]) {
+        for chunk in dst.chunks_mut(4) {
+            let bytes = self.next_u32().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use super::{RayIntegrator, sample_light};
+use glam::Vec3A;
+
+use crate::{
+    bsdf::TransportMode,
+    medium_stack::MediumStack,
+    pdf::power_heuristic,
+    rand,
+    ray::Ray,
+    scene::Scene,
+    vec::{Color, Vec3Ext},
+};
+
+/// Unidirectional path tracer with next-event estimation, MIS and Russian roulette.
+pub struct PathIntegrator {
+    max_depth: i32,
+    /// Number of bounces before Russian roulette may terminate a path.
+    roulette_depth: i32,
+}
+
+impl PathIntegrator {
+    pub fn new(max_depth: i32, roulette_depth: i32) -> Self {
+        Self {
+            max_depth,
+            roulette_depth,
+        }
+    }
+}
+
+impl RayIntegrator for PathIntegrator {
+    fn li(&self, ray: &Ray, scene: &Scene) -> Color {
+        let mut ray = *ray;
+        let mut color = Color::ZERO;
+        let mut throughput = Color::ONE;
+        // MIS weight of light found directly by `ray`, which depends on how
+        // the ray was sampled.
+        let mut emission_weight = 1.0;
+        let mut media = MediumStack::new();
+
+        for depth in 0..self.max_depth {
+            let Some((rec, transmittance)) = scene.hit_nested(&ray, &mut media) else {
+                color += throughput * emission_weight * scene.background().value(ray.direction());
+                break;
+            };
+            throughput *= transmittance;
+
+            let emitted = rec.material().emitted(rec.u(), rec.v(), rec.point());
+            color += throughput * emission_weight * emitted;
+
+            let Some(bsdf) = rec.material().bsdf(&rec) else {
+                break;
+            };
+            let wo = -ray.direction();
+
+            // Next-event estimation: sample the lights directly.
+            if bsdf.flags().is_non_specular() {
+                color += throughput * sample_light(&rec, &bsdf, wo, scene);
+            }
+
+            // Continue the path by sampling the BSDF.
+            let Some(sample) =
+                bsdf.sample(wo, Vec3A::random_range(0.0..1.0), TransportMode::Radiance)
+            else {
+                break;
+            };
+            if sample.pdf < 1e-16 {
+                break;
+            }
+            throughput *= sample.f / sample.pdf;
+            if sample.flags.is_specular() {
+                media.cross(&rec, ray.direction(), sample.wi);
+                emission_weight = 1.0;
+            } else {
+                emission_weight =
+                    power_heuristic(sample.pdf, scene.light_pdf(rec.point(), sample.wi));
+            }
+            ray = Ray::new(rec.point(), sample.wi);
+
+            if depth + 1 >= self.roulette_depth {
+                let survival = throughput.max_element().min(0.95);
+                if rand::random::<f32>() >= survival {
+                    break;
+                }
+                throughput /= survival;
+            }
+        }
+
+        color
+    }
+}
@@ -0,0 +1,37 @@
+use super::RayIntegrator;
+use crate::{
+    ray::Ray,
+    scene::Scene,
+    spectrum::{self, SampledWavelengths},
+    vec::Color,
+};
+
+/// Renders with another ray integrator in spectral mode: each camera ray
+/// carries a fresh set of hero wavelengths, and its spectral radiance is
+/// converted to RGB through CIE XYZ before it reaches the film. Dispersive
+/// dielectrics only produce colored refraction in this mode.
+///
+/// Only integrators that estimate camera rays independently can be wrapped;
+/// SPPM, light tracing and MLT always render in RGB.
+pub struct SpectralIntegrator<I> {
+    inner: I,
+}
+
+impl<I: RayIntegrator> SpectralIntegrator<I> {
+    pub fn new(inner: I) -> Self {
+        Self { inner }
+    }
+}
+
+impl<I: RayIntegrator> RayIntegrator for SpectralIntegrator<I> {
+    fn li(&self, ray: &Ray, scene: &Scene) -> Color {
+        // Fetched back after tracing, as the path may have terminated the
+        // secondary wavelengths.
+        let (radiance, wavelengths) =
+            spectrum::with_wavelengths(SampledWavelengths::sample(), || {
+                let radiance = self.inner.li(ray, scene);
+                (radiance, spectrum::current().unwrap())
+            });
+        wavelengths.to_rgb(radiance)
+    }
+}
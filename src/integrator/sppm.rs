@@ -0,0 +1,261 @@
+use std::f32;
+
+use glam::Vec3A;
+use indicatif::{ProgressBar, ProgressIterator};
+use rayon::iter::{IndexedParallelIterator, IntoParallelIterator, ParallelIterator};
+use rayon::slice::ParallelSliceMut;
+
+use super::{Integrator, camera_ray, estimate_direct, sample_light};
+use crate::{
+    bsdf::TransportMode,
+    camera::Camera,
+    film::Film,
+    medium_stack::MediumStack,
+    onb::ONB,
+    pdf::power_heuristic,
+    photon_map::{Photon, PhotonMap},
+    rand,
+    ray::Ray,
+    scene::Scene,
+    vec::{Color, Vec3Ext},
+};
+
+/// Fraction of the photons gathered in an iteration that are kept, which
+/// controls how fast the gather radius shrinks.
+const ALPHA: f32 = 2.0 / 3.0;
+
+/// Stochastic progressive photon mapping (Hachisuka and Jensen 2009).
+///
+/// Every iteration traces a fresh photon map from the explicit lights and
+/// the background, and a fresh camera ray per pixel, shrinking each pixel's
+/// gather radius as photons accumulate. Direct lighting at the visible point
+/// is estimated with light sampling, so photons are only deposited after
+/// their first bounce; specular-diffuse-specular paths such as caustics seen
+/// through glass come from the photons. Specular lobes of surfaces that also
+/// scatter diffusely, such as the coat of `Coated`, give direct light only.
+pub struct SPPMIntegrator {
+    max_depth: i32,
+    photons_per_iteration: usize,
+    initial_radius: f32,
+}
+
+impl SPPMIntegrator {
+    pub fn new(max_depth: i32, photons_per_iteration: usize, initial_radius: f32) -> Self {
+        Self {
+            max_depth,
+            photons_per_iteration,
+            initial_radius,
+        }
+    }
+
+    fn trace_photons(&self, scene: &Scene) -> Vec<Photon> {
+        (0..self.photons_per_iteration)
+            .into_par_iter()
+            .flat_map_iter(|_| self.trace_photon(scene))
+            .collect()
+    }
+
+    fn trace_photon(&self, scene: &Scene) -> Vec<Photon> {
+        let mut photons = vec![];
+        let from_background = !scene.has_light_surfaces() || rand::random();
+        let emitted = if from_background {
+            scene.sample_background_ray()
+        } else {
+            emit_from_light(scene)
+        };
+        let Some((mut ray, mut power)) = emitted else {
+            return photons;
+        };
+        if scene.has_light_surfaces() {
+            power *= 2.0;
+        }
+        let mut media = MediumStack::new();
+
+        for depth in 0..self.max_depth {
+            let Some((rec, transmittance)) = scene.hit_nested(&ray, &mut media) else {
+                break;
+            };
+            power *= transmittance;
+            // Light from the background cannot reach the inside of an
+            // object without crossing its surface first.
+            if depth == 0 && from_background && !rec.front_face() {
+                break;
+            }
+            let Some(bsdf) = rec.material().bsdf(&rec) else {
+                break;
+            };
+
+            // The gather only estimates light reflected by surfaces.
+            if depth > 0 && bsdf.flags().is_non_specular() && !rec.material().is_medium() {
+                photons.push(Photon {
+                    p: rec.point(),
+                    direction: ray.direction(),
+                    power,
+                });
+            }
+
+            let Some(sample) = bsdf.sample(
+                -ray.direction(),
+                Vec3A::random_range(0.0..1.0),
+                TransportMode::Importance,
+            ) else {
+                break;
+            };
+            if sample.pdf < 1e-16 {
+                break;
+            }
+            let next = power * sample.f / sample.pdf;
+            if sample.flags.is_specular() {
+                media.cross(&rec, ray.direction(), sample.wi);
+                power = next;
+            } else {
+                // Russian roulette keeps the photon power roughly constant.
+                let survival = (next.max_element() / power.max_element()).min(1.0);
+                if rand::random::<f32>() >= survival {
+                    break;
+                }
+                power = next / survival;
+            }
+            ray = Ray::new(rec.point(), sample.wi);
+        }
+
+        photons
+    }
+
+    /// Traces `ray` to its first diffuse surface hit, adding the light found
+    /// on the way and the photons around the hit to `pixel`. Scattering in
+    /// media is path traced with next-event estimation.
+    fn update_pixel(&self, pixel: &mut PixelState, mut ray: Ray, scene: &Scene, map: &PhotonMap) {
+        let mut beta = Color::ONE;
+        let mut emission_weight = 1.0;
+        let mut media = MediumStack::new();
+
+        for _ in 0..self.max_depth {
+            let Some((rec, transmittance)) = scene.hit_nested(&ray, &mut media) else {
+                pixel.ld += beta * emission_weight * scene.background().value(ray.direction());
+                return;
+            };
+            beta *= transmittance;
+
+            pixel.ld +=
+                beta * emission_weight * rec.material().emitted(rec.u(), rec.v(), rec.point());
+            let Some(bsdf) = rec.material().bsdf(&rec) else {
+                return;
+            };
+            let wo = -ray.direction();
+
+            if !bsdf.flags().is_non_specular() || rec.material().is_medium() {
+                if bsdf.flags().is_non_specular() {
+                    pixel.ld += beta * sample_light(&rec, &bsdf, wo, scene);
+                }
+                let Some(sample) =
+                    bsdf.sample(wo, Vec3A::random_range(0.0..1.0), TransportMode::Radiance)
+                else {
+                    return;
+                };
+                if sample.pdf < 1e-16 {
+                    return;
+                }
+                if sample.flags.is_specular() {
+                    media.cross(&rec, ray.direction(), sample.wi);
+                    emission_weight = 1.0;
+                } else {
+                    emission_weight =
+                        power_heuristic(sample.pdf, scene.light_pdf(rec.point(), sample.wi));
+                }
+                beta *= sample.f / sample.pdf;
+                ray = Ray::new(rec.point(), sample.wi);
+                continue;
+            }
+
+            pixel.ld += beta * estimate_direct(&rec, &bsdf, wo, scene);
+
+            let mut phi = Color::ZERO;
+            let mut count = 0.0;
+            map.for_each_within(rec.point(), pixel.radius, |photon| {
+                let wi = -photon.direction;
+                let cosine = rec.normal().dot(wi).abs();
+                if cosine <= 1e-4 {
+                    return;
+                }
+                let f = bsdf.eval(wo, wi, TransportMode::Radiance) / cosine;
+                phi += f * photon.power;
+                count += 1.0;
+            });
+
+            if count > 0.0 {
+                let n = pixel.n + ALPHA * count;
+                let radius = pixel.radius * f32::sqrt(n / (pixel.n + count));
+                pixel.tau =
+                    (pixel.tau + beta * phi) * (radius * radius) / (pixel.radius * pixel.radius);
+                pixel.n = n;
+                pixel.radius = radius;
+            }
+            return;
+        }
+    }
+}
+
+/// Samples the ray and power of a photon leaving one of the explicit lights.
+fn emit_from_light(scene: &Scene) -> Option<(Ray, Color)> {
+    let (light, pdf_area) = scene.sample_light_surface()?;
+
+    let direction = ONB::build_from_w(light.normal()).local(Vec3A::random_cosine_direction());
+    let cosine = light.normal().dot(direction);
+    let pdf_dir = cosine / f32::consts::PI;
+    if pdf_dir < 1e-16 {
+        return None;
+    }
+
+    let emitted = light
+        .material()
+        .emitted(light.u(), light.v(), light.point());
+    let power = emitted * cosine / (pdf_area * pdf_dir);
+    Some((Ray::new(light.point(), direction), power))
+}
+
+struct PixelState {
+    /// Sum of the light reaching the camera other than through photons.
+    ld: Color,
+    radius: f32,
+    /// Accumulated photon count, which grows by `ALPHA` per photon gathered.
+    n: f32,
+    /// Accumulated flux within the current radius.
+    tau: Color,
+}
+
+impl Integrator for SPPMIntegrator {
+    fn render(&self, scene: &Scene, camera: &Camera, film: &mut Film, samples_per_pixel: u32) {
+        let (width, height) = (film.width(), film.height());
+        let mut pixels: Vec<PixelState> = (0..width * height)
+            .map(|_| PixelState {
+                ld: Color::ZERO,
+                radius: self.initial_radius,
+                n: 0.0,
+                tau: Color::ZERO,
+            })
+            .collect();
+
+        let pb = ProgressBar::new(samples_per_pixel as u64);
+        for _ in (0..samples_per_pixel).progress_with(pb) {
+            let map = PhotonMap::build(self.trace_photons(scene));
+            pixels
+                .par_chunks_mut(width)
+                .enumerate()
+                .for_each(|(row_idx, row_slice)| {
+                    let j = height - 1 - row_idx;
+                    for (i, pixel) in row_slice.iter_mut().enumerate() {
+                        let ray = camera_ray(camera, i, j, width, height);
+                        self.update_pixel(pixel, ray, scene, &map);
+                    }
+                });
+        }
+
+        let iterations = samples_per_pixel as f32;
+        let photons = iterations * self.photons_per_iteration as f32;
+        for (pixel, color) in pixels.iter().zip(film.pixels_mut()) {
+            let area = f32::consts::PI * pixel.radius * pixel.radius;
+            *color = pixel.ld / iterations + pixel.tau / (photons * area);
+        }
+    }
+}
@@ -1,13 +1,28 @@
 pub mod aabb;
+pub mod background;
+pub mod bsdf;
 pub mod bvh;
 pub mod camera;
 pub mod color;
+pub mod constant_medium;
+pub mod film;
+pub mod heterogeneous_medium;
 pub mod hittable;
+pub mod integrator;
 pub mod material;
+pub mod medium_stack;
+pub mod microfacet;
 pub mod onb;
 pub mod pdf;
+pub mod perlin;
+pub mod photon_map;
 pub mod rand;
 pub mod ray;
+pub mod scene;
+pub mod sky;
+pub mod spectrum;
 pub mod sphere;
+pub mod subsurface;
 pub mod texture;
 pub mod vec;
+pub mod voxel_grid;
@@ -1,69 +1,53 @@
 use std::sync::Arc;
 
 use glam::Vec3A;
-use indicatif::{ParallelProgressIterator, ProgressBar};
 use ray_tracing::{
+    aabb::AABB,
+    background::Background,
     bvh::BVHBranch,
     camera::Camera,
-    color,
+    film::Film,
     hittable::{Geometry, HittableList},
-    material::{Dielectric, DiffuseLight, Lambertian, Material, Metal, ScatterEvent},
-    pdf::{HittablePDF, MixturePDF, PDF},
+    integrator::{
+        AmbientOcclusionIntegrator, BDPTIntegrator, DebugIntegrator, DebugMode,
+        DirectLightingIntegrator, Integrator, LightTracingIntegrator, MLTIntegrator,
+        PathIntegrator, SPPMIntegrator, SpectralIntegrator,
+    },
+    material::{Dielectric, Lambertian, Material, Metal},
     rand,
-    ray::Ray,
+    scene::Scene,
+    sky::Sky,
     sphere::Sphere,
     texture::SolidTexture,
     vec::{Color, Point3, Vec3Ext},
 };
-use rayon::{
-    iter::{IndexedParallelIterator, ParallelIterator},
-    slice::ParallelSliceMut,
-};
-
-fn ray_color(ray: &Ray, world: &HittableList, lights: Arc<Geometry>, depth: i32) -> Color {
-    if depth <= 0 {
-        return Color::ZERO;
-    }
-
-    let Some((_, rec)) = world.hit(ray, 0.001, f32::INFINITY) else {
-        return Color::new(1.0, 0.5, 0.0);
-    };
-
-    let emitted = rec.material().emitted(rec.u(), rec.v(), rec.point());
-
-    let Some(srec) = rec.material().scatter(ray, &rec) else {
-        return emitted;
-    };
-
-    match srec.event {
-        ScatterEvent::Specular(specular) => {
-            srec.attenuation * ray_color(&specular, world, lights, depth - 1)
-        }
-        ScatterEvent::Diffuse(pdf) => {
-            let light = HittablePDF::new(lights.clone(), rec.point());
-            let p = MixturePDF::new(Arc::new(light), pdf);
-
-            let scattered = Ray::new(rec.point(), p.generate());
-            let pdf_value = p.value(scattered.direction());
-
-            if pdf_value < 1e-16 {
-                return emitted;
-            }
 
-            emitted
-                + srec.attenuation
-                    * rec.material().scattering_pdf(ray, &rec, &scattered)
-                    * ray_color(&scattered, world, lights, depth - 1)
-                    / pdf_value
-        }
-    }
+fn select_integrator(name: &str) -> Option<Box<dyn Integrator>> {
+    let max_depth = 50;
+    let roulette_depth = 5;
+
+    Some(match name {
+        "path" => Box::new(PathIntegrator::new(max_depth, roulette_depth)),
+        "spectral" => Box::new(SpectralIntegrator::new(PathIntegrator::new(
+            max_depth,
+            roulette_depth,
+        ))),
+        "bdpt" => Box::new(BDPTIntegrator::new(max_depth as usize)),
+        "direct" => Box::new(DirectLightingIntegrator::new(max_depth)),
+        "sppm" => Box::new(SPPMIntegrator::new(max_depth, 100_000, 0.05)),
+        "light" => Box::new(LightTracingIntegrator::new(max_depth)),
+        "mlt" => Box::new(MLTIntegrator::new(max_depth, 100_000, 1000, 0.01, 0.3)),
+        "ao" => Box::new(AmbientOcclusionIntegrator::new(1.0)),
+        "normals" => Box::new(DebugIntegrator::new(DebugMode::Normals)),
+        "albedo" => Box::new(DebugIntegrator::new(DebugMode::Albedo)),
+        _ => return None,
+    })
 }
 
-fn random_scene() -> (HittableList, HittableList) {
+fn random_scene() -> Scene {
     let mut world = HittableList::new();
-    let ground_material = Material::Lambertian(Lambertian::new(Arc::new(SolidTexture::new(
-        0.5, 0.5, 0.5,
-    ))));
+    let ground_material =
+        Material::Lambertian(Lambertian::new(Arc::new(SolidTexture::new(0.5, 0.5, 0.5))));
     world.add(Geometry::Sphere(Sphere::new(
         Point3::new(0.0, -1000.0, 0.0),
         1000.0,
@@ -134,29 +118,30 @@ fn random_scene() -> (HittableList, HittableList) {
         material3,
     )));
 
-    let sun_light = DiffuseLight::new(Arc::new(SolidTexture::new(10.0, 10.0, 10.0)));
-    let sun = Sphere::new(
-        Point3::new(100.0, 100.0, 100.0),
-        50.0,
-        Material::DiffuseLight(sun_light),
-    );
-
-    world.add(Geometry::Sphere(sun.clone()));
+    let sky = Sky::new(6.0, 45.0, 3.0);
 
-    let mut lights = HittableList::new();
-    lights.add(Geometry::Sphere(sun));
-
-    (world, lights)
+    // Sunlight is traced towards the spheres rather than the whole ground.
+    let focus = AABB::new(Point3::new(-11.0, 0.0, -11.0), Point3::new(11.0, 2.0, 11.0));
+    Scene::new(world, HittableList::new(), Background::Sky(sky)).with_focus(focus)
 }
 
 fn main() {
     let mut stdout = std::io::stdout();
 
+    let integrator_name = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "path".to_string());
+    let Some(integrator) = select_integrator(&integrator_name) else {
+        eprintln!(
+            "unknown integrator `{integrator_name}`, expected one of: path, spectral, bdpt, sppm, mlt, light, direct, ao, normals, albedo"
+        );
+        std::process::exit(1);
+    };
+
     let aspect_ration = 16.0 / 9.0;
     let image_width = 1280;
-    let image_height = (image_width as f32 / aspect_ration) as i32;
+    let image_height = (image_width as f32 / aspect_ration) as usize;
     let samples_per_pixel = 1000;
-    let max_depth = 50;
 
     let lookfrom = Point3::new(13.0, 2.0, 6.0);
     let lookat = Point3::new(0.0, 0.0, 0.0);
@@ -170,33 +155,10 @@ fn main() {
         10.0,
     );
 
-    let (world, lights) = random_scene();
-    let lights = Arc::new(Geometry::List(lights));
-
-    let pb = ProgressBar::new(image_height as u64);
-
-    let mut pixels = vec![Color::ZERO; (image_width * image_height) as usize];
-    pixels
-        .par_chunks_mut(image_width as usize)
-        .enumerate()
-        .progress_with(pb)
-        .for_each(|(row_idx, row_slice)| {
-            let j = image_height - 1 - row_idx as i32;
-            for (i, pixel) in row_slice.iter_mut().enumerate() {
-                let mut pixel_color = Color::ZERO;
-                for _ in 0..samples_per_pixel {
-                    let u = (i as f32 + rand::random::<f32>()) / (image_width - 1) as f32;
-                    let v = (j as f32 + rand::random::<f32>()) / (image_height - 1) as f32;
-                    let ray = camera.get_ray(u, v);
-                    pixel_color += ray_color(&ray, &world, lights.clone(), max_depth);
-                }
-                *pixel = pixel_color;
-            }
-        });
+    let scene = random_scene();
 
-    print!("P3\n{} {}\n255\n", image_width, image_height);
-    for pixel_color in pixels {
-        color::write_color(&mut stdout, pixel_color, samples_per_pixel);
-    }
+    let mut film = Film::new(image_width, image_height);
+    integrator.render(&scene, &camera, &mut film, samples_per_pixel);
+    film.write_ppm(&mut stdout);
     eprintln!("\nDone.");
 }
@@ -1,60 +1,94 @@
 use std::{f32, sync::Arc};
 
-use glam::Vec3A;
-
 use crate::{
+    bsdf::{
+        BSDF, BxDF, CoatedBxDF, ConductorBxDF, DielectricBxDF, DiffuseBxDF, HenyeyGreensteinBxDF,
+        MetalBxDF, OrenNayarBxDF, PrincipledBxDF, RoughDielectricBxDF,
+    },
+    color,
     hittable::HitRecord,
-    pdf::{CosinePDF, PDF},
-    rand,
-    ray::Ray,
-    texture::Texture,
-    vec::{Color, Point3, Vec3Ext},
+    microfacet::{ThinFilm, TrowbridgeReitz},
+    onb::ONB,
+    rand, spectrum,
+    texture::{SolidTexture, Texture},
+    vec::{Color, Point3},
 };
 
 #[derive(Clone)]
 pub enum Material {
     Lambertian(Lambertian),
+    OrenNayar(OrenNayar),
     Metal(Metal),
+    Conductor(Conductor),
     Dielectric(Dielectric),
+    RoughDielectric(RoughDielectric),
+    Principled(Principled),
+    Coated(Coated),
+    Mix(Mix),
     DiffuseLight(DiffuseLight),
+    Isotropic(Isotropic),
+    HenyeyGreenstein(HenyeyGreenstein),
 }
 
 impl Material {
-    /// scatter calculates (color, ray_out, pdf).
-    pub fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+    /// The scattering function at `rec`, `None` for surfaces that only
+    /// emit light.
+    pub fn bsdf(&self, rec: &HitRecord) -> Option<BSDF> {
         match self {
-            Material::Lambertian(m) => m.scatter(r_in, rec),
-            Material::Metal(m) => m.scatter(r_in, rec),
-            Material::Dielectric(m) => m.scatter(r_in, rec),
-            Material::DiffuseLight(m) => m.scatter(r_in, rec),
+            Material::Lambertian(m) => m.bsdf(rec),
+            Material::OrenNayar(m) => m.bsdf(rec),
+            Material::Metal(m) => m.bsdf(rec),
+            Material::Conductor(m) => m.bsdf(rec),
+            Material::Dielectric(m) => m.bsdf(rec),
+            Material::RoughDielectric(m) => m.bsdf(rec),
+            Material::Principled(m) => m.bsdf(rec),
+            Material::Coated(m) => m.bsdf(rec),
+            Material::Mix(m) => m.bsdf(rec),
+            Material::DiffuseLight(m) => m.bsdf(rec),
+            Material::Isotropic(m) => m.bsdf(rec),
+            Material::HenyeyGreenstein(m) => m.bsdf(rec),
         }
     }
+
     pub fn emitted(&self, u: f32, v: f32, p: Point3) -> Color {
         match self {
             Material::Lambertian(m) => m.emitted(u, v, p),
+            Material::OrenNayar(m) => m.emitted(u, v, p),
             Material::Metal(m) => m.emitted(u, v, p),
+            Material::Conductor(m) => m.emitted(u, v, p),
             Material::Dielectric(m) => m.emitted(u, v, p),
+            Material::RoughDielectric(m) => m.emitted(u, v, p),
+            Material::Principled(m) => m.emitted(u, v, p),
+            Material::Coated(m) => m.emitted(u, v, p),
+            Material::Mix(m) => m.emitted(u, v, p),
             Material::DiffuseLight(m) => m.emitted(u, v, p),
+            Material::Isotropic(m) => m.emitted(u, v, p),
+            Material::HenyeyGreenstein(m) => m.emitted(u, v, p),
         }
     }
-    pub fn scattering_pdf(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f32 {
-        match self {
-            Material::Lambertian(m) => m.scattering_pdf(r_in, rec, scattered),
-            Material::Metal(m) => m.scattering_pdf(r_in, rec, scattered),
-            Material::Dielectric(m) => m.scattering_pdf(r_in, rec, scattered),
-            Material::DiffuseLight(m) => m.scattering_pdf(r_in, rec, scattered),
-        }
+    /// Whether hits with this material are scattering events inside a
+    /// participating medium rather than on a surface, in which case the
+    /// normal of the hit has no meaning.
+    pub fn is_medium(&self) -> bool {
+        matches!(self, Material::Isotropic(_) | Material::HenyeyGreenstein(_))
     }
 }
 
-pub enum ScatterEvent {
-    Specular(Ray),
-    Diffuse(Arc<dyn PDF>),
+/// A shading frame at `rec` whose +z is the normal, which faces the ray, and
+/// whose +x follows the tangent `dpdu` of the surface.
+fn frame(rec: &HitRecord) -> ONB {
+    ONB::build_from_w_and_u(rec.normal(), rec.dpdu())
 }
 
-pub struct ScatterRecord {
-    pub event: ScatterEvent,
-    pub attenuation: Color,
+/// A shading frame at `rec` whose +z points out of the surface whichever
+/// side the ray came from, and whose +x follows the tangent `dpdu`.
+fn outward_frame(rec: &HitRecord) -> ONB {
+    let normal = if rec.front_face() {
+        rec.normal()
+    } else {
+        -rec.normal()
+    };
+    ONB::build_from_w_and_u(normal, rec.dpdu())
 }
 
 #[derive(Clone)]
@@ -67,22 +101,43 @@ impl Lambertian {
         Self { albedo }
     }
 
-    pub fn scatter(&self, _: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
-        let attenuation = self.albedo.value(rec.u(), rec.v(), rec.point());
-        let pdf = Arc::new(CosinePDF::new(rec.normal()));
-        Some(ScatterRecord {
-            attenuation,
-            event: ScatterEvent::Diffuse(pdf),
-        })
+    pub fn bsdf(&self, rec: &HitRecord) -> Option<BSDF> {
+        let albedo = spectrum::from_rgb(self.albedo.value(rec.u(), rec.v(), rec.point()));
+        Some(BSDF::new(
+            frame(rec),
+            BxDF::Diffuse(DiffuseBxDF::new(albedo)),
+        ))
     }
 
-    pub fn scattering_pdf(&self, _: &Ray, rec: &HitRecord, scattered: &Ray) -> f32 {
-        let cosine = rec.normal().dot(scattered.direction().normalize());
-        if cosine < 0.0 {
-            0.0
-        } else {
-            cosine / f32::consts::PI
-        }
+    pub fn emitted(&self, _u: f32, _v: f32, _p: Point3) -> Color {
+        Color::ZERO
+    }
+}
+
+/// A rough diffuse surface such as clay, concrete or cloth, which looks
+/// flatter than `Lambertian` and brighter towards the light.
+///
+/// `sigma` is the standard deviation of the slope angle of the microfacets in
+/// radians, taken from the mean of the channels of a texture.
+#[derive(Clone)]
+pub struct OrenNayar {
+    albedo: Arc<dyn Texture>,
+    sigma: Arc<dyn Texture>,
+}
+
+impl OrenNayar {
+    pub fn new(albedo: Arc<dyn Texture>, sigma: Arc<dyn Texture>) -> Self {
+        Self { albedo, sigma }
+    }
+
+    pub fn bsdf(&self, rec: &HitRecord) -> Option<BSDF> {
+        let (u, v, p) = (rec.u(), rec.v(), rec.point());
+        let albedo = spectrum::from_rgb(self.albedo.value(u, v, p));
+        let sigma = self.sigma.value(u, v, p).element_sum() / 3.0;
+        Some(BSDF::new(
+            frame(rec),
+            BxDF::OrenNayar(OrenNayarBxDF::new(albedo, sigma)),
+        ))
     }
 
     pub fn emitted(&self, _u: f32, _v: f32, _p: Point3) -> Color {
@@ -104,88 +159,587 @@ impl Metal {
         }
     }
 
-    pub fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
-        let reflected = r_in.direction().reflect(rec.normal());
-        let specular_ray = Ray::new(
-            rec.point(),
-            reflected + self.fuzz * Vec3A::radom_in_unit_sphere(),
+    pub fn bsdf(&self, rec: &HitRecord) -> Option<BSDF> {
+        let albedo = spectrum::from_rgb(self.albedo);
+        Some(BSDF::new(
+            frame(rec),
+            BxDF::Metal(MetalBxDF::new(albedo, self.fuzz)),
+        ))
+    }
+
+    pub fn emitted(&self, _u: f32, _v: f32, _p: Point3) -> Color {
+        Color::ZERO
+    }
+}
+
+/// A rough metal with GGX microfacets and a complex refractive index
+/// `eta + i k`, given at the wavelengths of the red, green and blue
+/// primaries.
+#[derive(Clone)]
+pub struct Conductor {
+    eta: Color,
+    k: Color,
+    distribution: TrowbridgeReitz,
+    film: Option<ThinFilmLayer>,
+}
+
+impl Conductor {
+    /// `roughness` is the GGX alpha of the microfacets; zero gives a perfect
+    /// mirror.
+    pub fn new(eta: Color, k: Color, roughness: f32) -> Self {
+        Self {
+            eta,
+            k,
+            distribution: TrowbridgeReitz::new(roughness, roughness),
+            film: None,
+        }
+    }
+
+    /// Coats the metal with a film of `thickness` nanometers, such as the
+    /// oxide layer of heat-tinted steel, see `Dielectric::with_thin_film`.
+    pub fn with_thin_film(self, thickness: Arc<dyn Texture>, ref_idx: f32) -> Self {
+        Self {
+            film: Some(ThinFilmLayer::new(thickness, ref_idx)),
+            ..self
+        }
+    }
+
+    /// Sets different roughness along the tangent `dpdu` of the surface,
+    /// such as the parallels of a sphere, and across it, stretching
+    /// highlights along one of them.
+    pub fn with_roughness(self, roughness_u: f32, roughness_v: f32) -> Self {
+        Self {
+            distribution: TrowbridgeReitz::new(roughness_u, roughness_v),
+            ..self
+        }
+    }
+
+    pub fn gold(roughness: f32) -> Self {
+        Self::new(
+            Color::new(0.143, 0.374, 1.442),
+            Color::new(3.983, 2.385, 1.603),
+            roughness,
+        )
+    }
+
+    pub fn copper(roughness: f32) -> Self {
+        Self::new(
+            Color::new(0.200, 0.924, 1.102),
+            Color::new(3.912, 2.452, 2.142),
+            roughness,
+        )
+    }
+
+    pub fn aluminum(roughness: f32) -> Self {
+        Self::new(
+            Color::new(1.657, 0.880, 0.521),
+            Color::new(9.224, 6.270, 4.837),
+            roughness,
+        )
+    }
+
+    pub fn silver(roughness: f32) -> Self {
+        Self::new(
+            Color::new(0.155, 0.117, 0.138),
+            Color::new(4.828, 3.122, 2.147),
+            roughness,
+        )
+    }
+
+    pub fn bsdf(&self, rec: &HitRecord) -> Option<BSDF> {
+        let mut bxdf = ConductorBxDF::new(
+            spectrum::from_rgb_samples(self.eta),
+            spectrum::from_rgb_samples(self.k),
+            self.distribution,
         );
-        Some(ScatterRecord {
-            event: ScatterEvent::Specular(specular_ray),
-            attenuation: self.albedo,
-        })
+        if let Some(film) = &self.film {
+            bxdf = bxdf.with_thin_film(film.at(rec, 1.0));
+        }
+        Some(BSDF::new(frame(rec), BxDF::Conductor(bxdf)))
     }
 
     pub fn emitted(&self, _u: f32, _v: f32, _p: Point3) -> Color {
         Color::ZERO
     }
+}
+
+/// The refractive index of a dielectric as a function of wavelength.
+#[derive(Clone, Copy, PartialEq)]
+pub enum RefractiveIndex {
+    Constant(f32),
+    /// Cauchy's equation `n = a + b / λ²`, with λ in micrometers.
+    Cauchy {
+        a: f32,
+        b: f32,
+    },
+    /// The Sellmeier equation `n² = 1 + Σ bᵢ λ² / (λ² - cᵢ)`, with λ in
+    /// micrometers and `c` in square micrometers.
+    Sellmeier {
+        b: [f32; 3],
+        c: [f32; 3],
+    },
+}
+
+impl RefractiveIndex {
+    /// The wavelength in nanometers used for dispersive indices when
+    /// rendering RGB, the Fraunhofer d line.
+    pub const D_LINE: f32 = 587.56;
+
+    pub const VACUUM: Self = RefractiveIndex::Constant(1.0);
+
+    /// The index at `lambda` in nanometers.
+    pub fn at(&self, lambda: f32) -> f32 {
+        let l2 = (lambda * 1e-3).powi(2);
+        match *self {
+            RefractiveIndex::Constant(n) => n,
+            RefractiveIndex::Cauchy { a, b } => a + b / l2,
+            RefractiveIndex::Sellmeier { b, c } => {
+                let sum: f32 = b.iter().zip(c).map(|(b, c)| b * l2 / (l2 - c)).sum();
+                f32::sqrt(1.0 + sum)
+            }
+        }
+    }
 
-    pub fn scattering_pdf(&self, _r_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> f32 {
-        0.0
+    pub fn is_dispersive(&self) -> bool {
+        !matches!(self, RefractiveIndex::Constant(_))
+    }
+
+    /// The index at the wavelength being rendered, the hero wavelength in
+    /// spectral mode. A dispersive index only holds for the hero wavelength,
+    /// so materials refracting light with it must also call
+    /// `spectrum::terminate_secondary`.
+    pub fn current(&self) -> f32 {
+        match spectrum::current() {
+            Some(wavelengths) if self.is_dispersive() => self.at(wavelengths.hero()),
+            _ => self.at(Self::D_LINE),
+        }
     }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, PartialEq)]
 pub struct Dielectric {
-    ref_idx: f32,
+    ior: RefractiveIndex,
+    /// Which dielectric a region belongs to where several overlap, see
+    /// `MediumStack`.
+    priority: u32,
+    /// Absorption coefficient of the interior per unit distance.
+    absorption: Color,
+    film: Option<ThinFilmLayer>,
 }
 
 impl Dielectric {
     pub fn new(ref_idx: f32) -> Self {
-        Self { ref_idx }
+        Self::with_ior(RefractiveIndex::Constant(ref_idx))
     }
 
-    pub fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
-        let attenuation = Color::new(1.0, 1.0, 1.0);
-        let etai_over_etat = if rec.front_face() {
-            1.0 / self.ref_idx
-        } else {
-            self.ref_idx
+    /// A dielectric whose refractive index varies with wavelength, which
+    /// splits light into its colors in spectral mode.
+    pub fn with_ior(ior: RefractiveIndex) -> Self {
+        Self {
+            ior,
+            priority: 0,
+            absorption: Color::ZERO,
+            film: None,
+        }
+    }
+
+    pub fn cauchy(a: f32, b: f32) -> Self {
+        Self::with_ior(RefractiveIndex::Cauchy { a, b })
+    }
+
+    pub fn sellmeier(b: [f32; 3], c: [f32; 3]) -> Self {
+        Self::with_ior(RefractiveIndex::Sellmeier { b, c })
+    }
+
+    /// Schott N-BK7 crown glass.
+    pub fn bk7() -> Self {
+        Self::sellmeier(
+            [1.039_612, 0.231_792_34, 1.010_469_5],
+            [0.006_000_699, 0.020_017_914, 103.560_65],
+        )
+    }
+
+    /// Schott SF11 dense flint glass.
+    pub fn sf11() -> Self {
+        Self::sellmeier(
+            [1.737_597, 0.313_747_35, 1.898_781],
+            [0.013_188_707, 0.062_306_814, 155.236_3],
+        )
+    }
+
+    pub fn diamond() -> Self {
+        Self::sellmeier([0.3306, 4.3356, 0.0], [0.030_625, 0.011_236, 0.0])
+    }
+
+    pub fn with_priority(self, priority: u32) -> Self {
+        Self { priority, ..self }
+    }
+
+    /// Tints the interior following the Beer-Lambert law, with `absorption`
+    /// the coefficient per unit distance. Only paths tracked with a
+    /// `MediumStack` are absorbed.
+    pub fn with_absorption(self, absorption: Color) -> Self {
+        Self { absorption, ..self }
+    }
+
+    /// Tints the interior so that `transmittance` of the light is left after
+    /// travelling `distance` inside.
+    pub fn with_transmittance_at(self, transmittance: Color, distance: f32) -> Self {
+        assert!(distance > 0.0, "distance must be positive");
+        let transmittance = transmittance.clamp(Color::splat(1e-6), Color::ONE);
+        self.with_absorption(-transmittance.map(f32::ln) / distance)
+    }
+
+    /// Coats the surface with a film of `thickness` nanometers and
+    /// refractive index `ref_idx`, whose interference colors reflections
+    /// like a soap bubble. A hollow bubble is a film on a dielectric with
+    /// the index of the air inside.
+    pub fn with_thin_film(self, thickness: Arc<dyn Texture>, ref_idx: f32) -> Self {
+        Self {
+            film: Some(ThinFilmLayer::new(thickness, ref_idx)),
+            ..self
+        }
+    }
+
+    pub fn ior(&self) -> RefractiveIndex {
+        self.ior
+    }
+
+    pub fn priority(&self) -> u32 {
+        self.priority
+    }
+
+    pub fn absorption(&self) -> Color {
+        self.absorption
+    }
+
+    pub fn bsdf(&self, rec: &HitRecord) -> Option<BSDF> {
+        // Without medium tracking the other side is assumed to be vacuum.
+        let (incident, transmitted) = match rec.iors() {
+            Some(iors) => iors,
+            None if rec.front_face() => (RefractiveIndex::VACUUM, self.ior),
+            None => (self.ior, RefractiveIndex::VACUUM),
         };
+        if incident.is_dispersive() || transmitted.is_dispersive() {
+            spectrum::terminate_secondary();
+        }
+        let (incident, transmitted) = (incident.current(), transmitted.current());
+        let mut bxdf = DielectricBxDF::new(transmitted / incident);
+        if let Some(film) = &self.film {
+            bxdf = bxdf.with_thin_film(film.at(rec, incident));
+        }
+        Some(BSDF::new(frame(rec), BxDF::Dielectric(bxdf)))
+    }
+
+    pub fn emitted(&self, _u: f32, _v: f32, _p: Point3) -> Color {
+        Color::ZERO
+    }
+}
+
+/// A film on the surface of a `Dielectric` or a `Conductor`, with its
+/// thickness in nanometers from the mean of the channels of a texture.
+#[derive(Clone)]
+struct ThinFilmLayer {
+    thickness: Arc<dyn Texture>,
+    ref_idx: f32,
+}
+
+impl ThinFilmLayer {
+    fn new(thickness: Arc<dyn Texture>, ref_idx: f32) -> Self {
+        Self { thickness, ref_idx }
+    }
 
-        let unit_direction = r_in.direction();
-        let cos_theta = -unit_direction.dot(rec.normal()).min(1.0);
-        let sin_theta = f32::sqrt(1.0 - cos_theta * cos_theta);
-        if etai_over_etat * sin_theta > 1.0 {
-            let reflected = unit_direction.reflect(rec.normal());
-            let scattered = Ray::new(rec.point(), reflected);
-            return Some(ScatterRecord {
-                event: ScatterEvent::Specular(scattered),
-                attenuation,
-            });
+    /// The film at `rec`, seen from a medium of refractive index
+    /// `incident`.
+    fn at(&self, rec: &HitRecord, incident: f32) -> ThinFilm {
+        let thickness = self
+            .thickness
+            .value(rec.u(), rec.v(), rec.point())
+            .element_sum()
+            / 3.0;
+        ThinFilm::new(thickness, self.ref_idx / incident, spectrum::lambda())
+    }
+}
+
+impl PartialEq for ThinFilmLayer {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.thickness, &other.thickness) && self.ref_idx == other.ref_idx
+    }
+}
+
+/// Frosted glass: a dielectric interface with GGX microfacets that both
+/// reflect and transmit light (Walter et al. 2007).
+///
+/// The roughness is the GGX alpha, taken from the mean of the channels of a
+/// texture. Like `Dielectric` without a `MediumStack`, the outside is
+/// assumed to be vacuum. Transmitted radiance is scaled by the squared ratio
+/// of the indices like that of `Dielectric`, see `TransportMode`.
+#[derive(Clone)]
+pub struct RoughDielectric {
+    ior: RefractiveIndex,
+    roughness: Arc<dyn Texture>,
+}
+
+impl RoughDielectric {
+    pub fn new(ref_idx: f32, roughness: Arc<dyn Texture>) -> Self {
+        Self::with_ior(RefractiveIndex::Constant(ref_idx), roughness)
+    }
+
+    pub fn with_ior(ior: RefractiveIndex, roughness: Arc<dyn Texture>) -> Self {
+        Self { ior, roughness }
+    }
+
+    fn distribution(&self, rec: &HitRecord) -> TrowbridgeReitz {
+        let alpha = self
+            .roughness
+            .value(rec.u(), rec.v(), rec.point())
+            .element_sum()
+            / 3.0;
+        TrowbridgeReitz::new(alpha, alpha)
+    }
+
+    pub fn bsdf(&self, rec: &HitRecord) -> Option<BSDF> {
+        let distribution = self.distribution(rec);
+        if distribution.is_smooth() {
+            return Dielectric::with_ior(self.ior).bsdf(rec);
+        }
+        if self.ior.is_dispersive() {
+            spectrum::terminate_secondary();
+        }
+        let bxdf = RoughDielectricBxDF::new(self.ior.current(), distribution);
+        Some(BSDF::new(outward_frame(rec), BxDF::RoughDielectric(bxdf)))
+    }
+
+    pub fn emitted(&self, _u: f32, _v: f32, _p: Point3) -> Color {
+        Color::ZERO
+    }
+}
+
+/// Disney's principled BSDF (Burley 2012, 2015): one material blending a
+/// diffuse base with sheen, GGX specular reflection, rough transmission and
+/// a clear coat.
+///
+/// Every parameter comes from a texture. Scalar parameters are the mean of
+/// the channels and range from zero to one.
+#[derive(Clone)]
+pub struct Principled {
+    base_color: Arc<dyn Texture>,
+    metallic: Arc<dyn Texture>,
+    roughness: Arc<dyn Texture>,
+    specular: Arc<dyn Texture>,
+    specular_tint: Arc<dyn Texture>,
+    sheen: Arc<dyn Texture>,
+    clearcoat: Arc<dyn Texture>,
+    transmission: Arc<dyn Texture>,
+    anisotropic: Arc<dyn Texture>,
+}
+
+impl Principled {
+    /// An opaque dielectric of medium roughness with the given color.
+    pub fn new(base_color: Arc<dyn Texture>) -> Self {
+        let constant = |v: f32| -> Arc<dyn Texture> { Arc::new(SolidTexture::new(v, v, v)) };
+        Self {
+            base_color,
+            metallic: constant(0.0),
+            roughness: constant(0.5),
+            specular: constant(0.5),
+            specular_tint: constant(0.0),
+            sheen: constant(0.0),
+            clearcoat: constant(0.0),
+            transmission: constant(0.0),
+            anisotropic: constant(0.0),
+        }
+    }
+
+    /// Blends towards a conductor reflecting the base color.
+    pub fn with_metallic(self, metallic: Arc<dyn Texture>) -> Self {
+        Self { metallic, ..self }
+    }
+
+    pub fn with_roughness(self, roughness: Arc<dyn Texture>) -> Self {
+        Self { roughness, ..self }
+    }
+
+    /// Scales the specular reflectance of dielectrics, where 0.5 is the 4%
+    /// of an index of 1.5. Also sets the index of the transmission.
+    pub fn with_specular(self, specular: Arc<dyn Texture>) -> Self {
+        Self { specular, ..self }
+    }
+
+    /// Tints the specular reflection of dielectrics towards the base color.
+    pub fn with_specular_tint(self, specular_tint: Arc<dyn Texture>) -> Self {
+        Self {
+            specular_tint,
+            ..self
+        }
+    }
+
+    /// Adds the soft reflection of cloth at grazing angles.
+    pub fn with_sheen(self, sheen: Arc<dyn Texture>) -> Self {
+        Self { sheen, ..self }
+    }
+
+    /// Adds a glossy clear coat on top.
+    pub fn with_clearcoat(self, clearcoat: Arc<dyn Texture>) -> Self {
+        Self { clearcoat, ..self }
+    }
+
+    /// Blends the dielectric base towards rough glass tinted by the base
+    /// color.
+    pub fn with_transmission(self, transmission: Arc<dyn Texture>) -> Self {
+        Self {
+            transmission,
+            ..self
+        }
+    }
+
+    /// Stretches highlights along the tangent `dpdu` of the surface, such as
+    /// the parallels of a sphere.
+    pub fn with_anisotropic(self, anisotropic: Arc<dyn Texture>) -> Self {
+        Self {
+            anisotropic,
+            ..self
         }
+    }
+
+    fn bxdf(&self, rec: &HitRecord) -> PrincipledBxDF {
+        let (u, v, p) = (rec.u(), rec.v(), rec.point());
+        let scalar = |t: &Arc<dyn Texture>| (t.value(u, v, p).element_sum() / 3.0).clamp(0.0, 1.0);
+        let metallic = scalar(&self.metallic);
+        let roughness = scalar(&self.roughness);
+        let specular = scalar(&self.specular);
+        let transmission = scalar(&self.transmission);
+
+        let base = self.base_color.value(u, v, p);
+        let luminance = color::luminance(base);
+        let tint = if luminance > 0.0 {
+            base / luminance
+        } else {
+            Color::ONE
+        };
+        let specular_tint = Color::ONE.lerp(tint, scalar(&self.specular_tint));
+        let specular0 = (0.08 * specular * specular_tint).lerp(base, metallic);
+        let sheen = scalar(&self.sheen) * Color::ONE.lerp(tint, 0.5);
+
+        let aspect = f32::sqrt(1.0 - 0.9 * scalar(&self.anisotropic));
+        let alpha = roughness * roughness;
+        let distribution =
+            TrowbridgeReitz::new((alpha / aspect).max(1e-3), (alpha * aspect).max(1e-3));
 
-        let reflect_prob = schlick(cos_theta, etai_over_etat);
-        if rand::random::<f32>() < reflect_prob {
-            let reflected = unit_direction.reflect(rec.normal());
-            let scattered = Ray::new(rec.point(), reflected);
-            return Some(ScatterRecord {
-                event: ScatterEvent::Specular(scattered),
-                attenuation,
-            });
+        PrincipledBxDF {
+            base: spectrum::from_rgb(base),
+            sheen: spectrum::from_rgb(sheen),
+            specular0: spectrum::from_rgb(specular0),
+            roughness,
+            diffuse_weight: (1.0 - metallic) * (1.0 - transmission),
+            specular_weight: 1.0 - (1.0 - metallic) * transmission,
+            glass_weight: (1.0 - metallic) * transmission,
+            clearcoat_weight: 0.25 * scalar(&self.clearcoat),
+            eta: (2.0 / (1.0 - f32::sqrt(0.08 * specular)) - 1.0).max(1.01),
+            distribution,
         }
+    }
 
-        let refracted = unit_direction.refract(rec.normal(), etai_over_etat);
-        let scattered = Ray::new(rec.point(), refracted);
-        Some(ScatterRecord {
-            event: ScatterEvent::Specular(scattered),
-            attenuation,
-        })
+    pub fn bsdf(&self, rec: &HitRecord) -> Option<BSDF> {
+        Some(BSDF::new(
+            outward_frame(rec),
+            BxDF::Principled(self.bxdf(rec)),
+        ))
     }
 
     pub fn emitted(&self, _u: f32, _v: f32, _p: Point3) -> Color {
         Color::ZERO
     }
+}
+
+/// A material under a smooth dielectric coat, such as varnished wood or the
+/// clear coat of car paint. The coat reflects specularly and lets the rest
+/// of the light through to the base, following the single scattering model
+/// of Weidlich and Wilkie (2007). The base is seen from above only.
+#[derive(Clone)]
+pub struct Coated {
+    base: Arc<Material>,
+    ior: RefractiveIndex,
+    absorption: Color,
+    thickness: f32,
+}
+
+impl Coated {
+    pub fn new(base: Material, ref_idx: f32) -> Self {
+        Self::with_ior(base, RefractiveIndex::Constant(ref_idx))
+    }
 
-    pub fn scattering_pdf(&self, _r_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> f32 {
-        0.0
+    pub fn with_ior(base: Material, ior: RefractiveIndex) -> Self {
+        Self {
+            base: Arc::new(base),
+            ior,
+            absorption: Color::ZERO,
+            thickness: 0.0,
+        }
+    }
+
+    /// Tints the coat following the Beer-Lambert law, with `absorption` the
+    /// coefficient per unit distance and `thickness` the depth of the coat.
+    pub fn with_absorption(self, absorption: Color, thickness: f32) -> Self {
+        Self {
+            absorption,
+            thickness,
+            ..self
+        }
+    }
+
+    pub fn bsdf(&self, rec: &HitRecord) -> Option<BSDF> {
+        let base = self.base.bsdf(rec)?;
+        if self.ior.is_dispersive() {
+            spectrum::terminate_secondary();
+        }
+        let depth = spectrum::from_rgb(self.absorption) * self.thickness;
+        let bxdf = CoatedBxDF::new(self.ior.current(), depth, base.bxdf().clone());
+        Some(BSDF::new(base.frame(), BxDF::Coated(bxdf)))
     }
+
+    pub fn emitted(&self, u: f32, v: f32, p: Point3) -> Color {
+        self.base.emitted(u, v, p)
+    }
+}
+
+/// A blend of two materials driven by a texture, for masks such as rust on
+/// metal or dirt on paint. The weight of `b` is the mean of the channels of
+/// the texture, and each hit scatters like one of the two materials chosen
+/// at random by the weight. Dielectrics inside a mix are not tracked by
+/// `MediumStack`.
+#[derive(Clone)]
+pub struct Mix {
+    a: Arc<Material>,
+    b: Arc<Material>,
+    weight: Arc<dyn Texture>,
 }
 
-fn schlick(cos: f32, ref_idx: f32) -> f32 {
-    let mut r0 = (1.0 - ref_idx) / (1.0 + ref_idx);
-    r0 = r0 * r0;
-    r0 + (1.0 - r0) * (1.0 - cos).powi(5)
+impl Mix {
+    pub fn new(a: Material, b: Material, weight: Arc<dyn Texture>) -> Self {
+        Self {
+            a: Arc::new(a),
+            b: Arc::new(b),
+            weight,
+        }
+    }
+
+    fn weight(&self, u: f32, v: f32, p: Point3) -> f32 {
+        (self.weight.value(u, v, p).element_sum() / 3.0).clamp(0.0, 1.0)
+    }
+
+    pub fn bsdf(&self, rec: &HitRecord) -> Option<BSDF> {
+        if rand::random::<f32>() < self.weight(rec.u(), rec.v(), rec.point()) {
+            self.b.bsdf(rec)
+        } else {
+            self.a.bsdf(rec)
+        }
+    }
+
+    pub fn emitted(&self, u: f32, v: f32, p: Point3) -> Color {
+        let weight = self.weight(u, v, p);
+        (1.0 - weight) * self.a.emitted(u, v, p) + weight * self.b.emitted(u, v, p)
+    }
 }
 
 #[derive(Clone)]
@@ -198,15 +752,64 @@ impl DiffuseLight {
         Self { emit }
     }
 
-    pub fn scatter(&self, _: &Ray, _: &HitRecord) -> Option<ScatterRecord> {
+    pub fn bsdf(&self, _: &HitRecord) -> Option<BSDF> {
         None
     }
 
     pub fn emitted(&self, u: f32, v: f32, p: Point3) -> Color {
-        self.emit.value(u, v, p)
+        spectrum::from_rgb(self.emit.value(u, v, p))
+    }
+}
+
+/// The phase function of a medium scattering equally in all directions.
+#[derive(Clone)]
+pub struct Isotropic {
+    albedo: Arc<dyn Texture>,
+}
+
+impl Isotropic {
+    pub fn new(albedo: Arc<dyn Texture>) -> Self {
+        Self { albedo }
+    }
+
+    pub fn bsdf(&self, rec: &HitRecord) -> Option<BSDF> {
+        let albedo = spectrum::from_rgb(self.albedo.value(rec.u(), rec.v(), rec.point()));
+        Some(BSDF::new(
+            frame(rec),
+            BxDF::HenyeyGreenstein(HenyeyGreensteinBxDF::new(albedo, 0.0)),
+        ))
+    }
+
+    pub fn emitted(&self, _u: f32, _v: f32, _p: Point3) -> Color {
+        Color::ZERO
+    }
+}
+
+/// The Henyey-Greenstein phase function of a medium, scattering forward for
+/// positive `g` and backward for negative `g`.
+#[derive(Clone)]
+pub struct HenyeyGreenstein {
+    albedo: Arc<dyn Texture>,
+    g: f32,
+}
+
+impl HenyeyGreenstein {
+    pub fn new(albedo: Arc<dyn Texture>, g: f32) -> Self {
+        Self {
+            albedo,
+            g: g.clamp(-0.99, 0.99),
+        }
     }
 
-    pub fn scattering_pdf(&self, _r_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> f32 {
-        0.0
+    pub fn bsdf(&self, rec: &HitRecord) -> Option<BSDF> {
+        let albedo = spectrum::from_rgb(self.albedo.value(rec.u(), rec.v(), rec.point()));
+        Some(BSDF::new(
+            frame(rec),
+            BxDF::HenyeyGreenstein(HenyeyGreensteinBxDF::new(albedo, self.g)),
+        ))
+    }
+
+    pub fn emitted(&self, _u: f32, _v: f32, _p: Point3) -> Color {
+        Color::ZERO
     }
 }
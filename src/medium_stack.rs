@@ -0,0 +1,88 @@
+use glam::Vec3A;
+
+use crate::{
+    hittable::HitRecord,
+    material::{Dielectric, Material, RefractiveIndex},
+    spectrum,
+    vec::Color,
+};
+
+/// The dielectrics a path is inside, for nested dielectrics with priorities
+/// (Schmidt and Budge 2002).
+///
+/// Where objects overlap, such as the water and the wall of a glass, the
+/// region belongs to the one with the highest priority, and the surfaces of
+/// the others inside it are false intersections that paths pass through.
+/// Paths are assumed to start outside of every dielectric.
+#[derive(Clone, Default)]
+pub struct MediumStack {
+    entries: Vec<Dielectric>,
+}
+
+impl MediumStack {
+    pub fn new() -> Self {
+        Self { entries: vec![] }
+    }
+
+    /// The dielectric the path is currently travelling through: the one with
+    /// the highest priority, or the most recently entered among equals.
+    pub fn current(&self) -> Option<&Dielectric> {
+        self.entries.iter().max_by_key(|d| d.priority())
+    }
+
+    /// The fraction of light not absorbed over `distance` in the current
+    /// dielectric.
+    pub fn transmittance(&self, distance: f32) -> Color {
+        self.current().map_or(Color::ONE, |d| {
+            (-spectrum::from_rgb(d.absorption()) * distance).exp()
+        })
+    }
+
+    /// The refractive indices on the incident and transmitted side of a hit
+    /// on `dielectric`, or `None` for a false intersection.
+    pub fn interface(
+        &self,
+        dielectric: &Dielectric,
+        entering: bool,
+    ) -> Option<(RefractiveIndex, RefractiveIndex)> {
+        let ior = |d: Option<&Dielectric>| d.map_or(RefractiveIndex::VACUUM, |d| d.ior());
+        if entering {
+            let current = self.current();
+            if current.is_some_and(|c| c.priority() > dielectric.priority()) {
+                return None;
+            }
+            Some((ior(current), dielectric.ior()))
+        } else {
+            let mut rest = self.clone();
+            rest.exit(dielectric);
+            let outside = rest.current();
+            if outside.is_some_and(|o| o.priority() > dielectric.priority()) {
+                return None;
+            }
+            Some((dielectric.ior(), ior(outside)))
+        }
+    }
+
+    /// Updates the stack for a path crossing the surface of `rec` from
+    /// `incoming` to `outgoing`. Reflections and other materials are ignored.
+    pub fn cross(&mut self, rec: &HitRecord, incoming: Vec3A, outgoing: Vec3A) {
+        let Material::Dielectric(dielectric) = rec.material() else {
+            return;
+        };
+        let transmitted = incoming.dot(rec.normal()) * outgoing.dot(rec.normal()) > 0.0;
+        if !transmitted {
+            return;
+        }
+        if rec.front_face() {
+            self.entries.push(dielectric.clone());
+        } else {
+            self.exit(dielectric);
+        }
+    }
+
+    fn exit(&mut self, dielectric: &Dielectric) {
+        if let Some(i) = self.entries.iter().rposition(|d| d == dielectric) {
+            self.entries.remove(i);
+        }
+    }
+}
@@ -0,0 +1,361 @@
+//! Microfacet distributions and Fresnel equations.
+//!
+//! Directions are given in a local shading frame where the surface normal is
+//! +z, as produced by `ONB::to_local`.
+
+use std::{
+    f32,
+    ops::{Add, Div, Mul, Sub},
+};
+
+use glam::{Vec2, Vec3A};
+
+use crate::{bsdf::TransportMode, vec::Color};
+
+/// The Trowbridge-Reitz (GGX) distribution of microfacet normals, with
+/// separate roughness along the tangent (`alpha_x`) and bitangent
+/// (`alpha_y`) directions.
+#[derive(Clone, Copy, PartialEq)]
+pub struct TrowbridgeReitz {
+    alpha_x: f32,
+    alpha_y: f32,
+}
+
+impl TrowbridgeReitz {
+    pub const fn new(alpha_x: f32, alpha_y: f32) -> Self {
+        Self { alpha_x, alpha_y }
+    }
+
+    /// Whether the surface is so smooth that it should be treated as a
+    /// perfect specular interface, where the distribution is numerically
+    /// unusable.
+    pub fn is_smooth(&self) -> bool {
+        self.alpha_x.max(self.alpha_y) < 1e-3
+    }
+
+    /// The density of microfacets with normal `wm`, per unit projected area.
+    pub fn d(&self, wm: Vec3A) -> f32 {
+        let cos2 = wm.z * wm.z;
+        if cos2 <= 0.0 {
+            return 0.0;
+        }
+        let e = (wm.x / self.alpha_x).powi(2) + (wm.y / self.alpha_y).powi(2) + cos2;
+        1.0 / (f32::consts::PI * self.alpha_x * self.alpha_y * e * e)
+    }
+
+    /// Smith's auxiliary function, the projected area of microfacets facing
+    /// away from `w` relative to those facing it.
+    fn lambda(&self, w: Vec3A) -> f32 {
+        let cos2 = w.z * w.z;
+        if cos2 <= 0.0 {
+            return f32::INFINITY;
+        }
+        let alpha2_tan2 = ((w.x * self.alpha_x).powi(2) + (w.y * self.alpha_y).powi(2)) / cos2;
+        (f32::sqrt(1.0 + alpha2_tan2) - 1.0) / 2.0
+    }
+
+    /// The fraction of microfacets visible from `w`.
+    pub fn g1(&self, w: Vec3A) -> f32 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    /// The fraction of microfacets visible from both `wo` and `wi`, using the
+    /// height-correlated Smith masking-shadowing function.
+    pub fn g(&self, wo: Vec3A, wi: Vec3A) -> f32 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// The density of microfacet normals visible from `w`.
+    pub fn d_visible(&self, w: Vec3A, wm: Vec3A) -> f32 {
+        if w.z == 0.0 {
+            return 0.0;
+        }
+        self.g1(w) / w.z.abs() * self.d(wm) * w.dot(wm).abs()
+    }
+
+    /// The scattering function of a rough interface between dielectrics,
+    /// with `eta` the ratio of the refractive index below the surface to
+    /// the one above. Transmitted radiance is scaled as described at
+    /// `TransportMode`.
+    pub fn dielectric_f(&self, wo: Vec3A, wi: Vec3A, eta: f32, mode: TransportMode) -> f32 {
+        let Some((wm, etap)) = generalized_half_vector(wo, wi, eta) else {
+            return 0.0;
+        };
+        let r = fresnel_dielectric(wo.dot(wm), eta);
+        let d = self.d(wm);
+        let g = self.g(wo, wi);
+        if etap == 1.0 {
+            d * r * g / (4.0 * wi.z * wo.z).abs()
+        } else {
+            let denom = (wi.dot(wm) + wo.dot(wm) / etap).powi(2);
+            let f = d * (1.0 - r) * g * (wi.dot(wm) * wo.dot(wm) / (wi.z * wo.z * denom)).abs();
+            match mode {
+                TransportMode::Radiance => f / (etap * etap),
+                TransportMode::Importance => f,
+            }
+        }
+    }
+
+    /// Samples a microfacet normal visible from `w` with density
+    /// `d_visible` (Heitz 2018), with `u` uniform over the unit square.
+    pub fn sample_wm(&self, w: Vec3A, u: Vec2) -> Vec3A {
+        // Stretch to the configuration where the distribution is a
+        // hemisphere, and work on the side of `w`.
+        let mut wh = Vec3A::new(self.alpha_x * w.x, self.alpha_y * w.y, w.z).normalize();
+        if wh.z < 0.0 {
+            wh = -wh;
+        }
+        let t1 = if wh.z < 0.99999 {
+            Vec3A::Z.cross(wh).normalize()
+        } else {
+            Vec3A::X
+        };
+        let t2 = wh.cross(t1);
+
+        // A uniform point on the disk, warped towards the visible half.
+        let r = u.x.sqrt();
+        let phi = 2.0 * f32::consts::PI * u.y;
+        let mut p = Vec2::new(r * phi.cos(), r * phi.sin());
+        let h = f32::sqrt(1.0 - p.x * p.x);
+        p.y = (1.0 - (1.0 + wh.z) / 2.0) * h + (1.0 + wh.z) / 2.0 * p.y;
+
+        let pz = f32::sqrt((1.0 - p.length_squared()).max(0.0));
+        let nh = p.x * t1 + p.y * t2 + pz * wh;
+        Vec3A::new(self.alpha_x * nh.x, self.alpha_y * nh.y, nh.z.max(1e-6)).normalize()
+    }
+}
+
+/// The microfacet normal scattering `wo` into `wi`, facing +z, together
+/// with the relative refractive index of the scattering: one for
+/// reflection, otherwise the ratio of the index on the side of `wi` to the
+/// one on the side of `wo`. `None` if no visible microfacet scatters `wo`
+/// into `wi`.
+pub fn generalized_half_vector(wo: Vec3A, wi: Vec3A, eta: f32) -> Option<(Vec3A, f32)> {
+    if wo.z == 0.0 || wi.z == 0.0 {
+        return None;
+    }
+    let etap = match (wo.z * wi.z > 0.0, wo.z > 0.0) {
+        (true, _) => 1.0,
+        (false, true) => eta,
+        (false, false) => 1.0 / eta,
+    };
+    let wm = (wi * etap + wo).normalize_or_zero();
+    if wm == Vec3A::ZERO {
+        return None;
+    }
+    let wm = if wm.z < 0.0 { -wm } else { wm };
+    // Microfacets seen from behind by either direction.
+    if wm.dot(wi) * wi.z < 0.0 || wm.dot(wo) * wo.z < 0.0 {
+        return None;
+    }
+    Some((wm, etap))
+}
+
+/// Fresnel reflectance of a conductor with complex refractive index
+/// `eta + i k`, for light arriving at `cos_theta` to the normal.
+pub fn fresnel_conductor(cos_theta: f32, eta: Color, k: Color) -> Color {
+    let cos_theta = cos_theta.clamp(0.0, 1.0);
+    let cos2 = cos_theta * cos_theta;
+    let sin2 = 1.0 - cos2;
+    let eta2 = eta * eta;
+    let k2 = k * k;
+
+    let t0 = eta2 - k2 - sin2;
+    let a2_plus_b2 = (t0 * t0 + 4.0 * eta2 * k2).map(f32::sqrt);
+    let t1 = a2_plus_b2 + cos2;
+    let a = (0.5 * (a2_plus_b2 + t0)).max(Color::ZERO).map(f32::sqrt);
+    let t2 = 2.0 * cos_theta * a;
+    let rs = (t1 - t2) / (t1 + t2);
+
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+
+    0.5 * (rp + rs)
+}
+
+/// Fresnel reflectance of an interface between dielectrics, where `eta` is
+/// the ratio of the refractive index below the surface to the one above,
+/// for light arriving at `cos_theta` to the normal. Light from below has a
+/// negative `cos_theta`.
+pub fn fresnel_dielectric(cos_theta: f32, eta: f32) -> f32 {
+    let (cos_i, eta) = if cos_theta < 0.0 {
+        (-cos_theta.max(-1.0), 1.0 / eta)
+    } else {
+        (cos_theta.min(1.0), eta)
+    };
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = f32::sqrt(1.0 - sin2_t);
+    let r_parallel = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let r_perpendicular = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    (r_parallel * r_parallel + r_perpendicular * r_perpendicular) / 2.0
+}
+
+/// A film a few hundred nanometers thick on top of an interface, whose
+/// reflections interfere with those of the interface and give the colors of
+/// soap bubbles and oil slicks.
+#[derive(Clone, Copy, PartialEq)]
+pub struct ThinFilm {
+    /// The thickness in nanometers.
+    thickness: f32,
+    /// The refractive index of the film relative to the medium above it.
+    eta: f32,
+    /// The wavelength in nanometers of each lane of `Color`.
+    lambda: Color,
+}
+
+impl ThinFilm {
+    pub fn new(thickness: f32, eta: f32, lambda: Color) -> Self {
+        Self {
+            thickness,
+            eta,
+            lambda,
+        }
+    }
+
+    /// The same film seen from below an interface, where `eta` is as in
+    /// `fresnel_dielectric`.
+    pub fn from_below(self, eta: f32) -> Self {
+        Self {
+            eta: self.eta / eta,
+            ..self
+        }
+    }
+
+    /// Reflectance of the film on an interface with complex refractive
+    /// index `eta + i k` below it, for light arriving at `cos_theta` to the
+    /// normal, using the Airy summation of the reflections inside the film.
+    /// A dielectric has a `k` of zero.
+    pub fn reflectance(&self, cos_theta: f32, eta: Color, k: Color) -> Color {
+        let cos_theta = cos_theta.clamp(0.0, 1.0);
+        let sin2 = Complex::real(1.0 - cos_theta * cos_theta);
+        Color::from_array(std::array::from_fn(|i| {
+            let n0 = Complex::real(1.0);
+            let n1 = Complex::real(self.eta);
+            let n2 = Complex::new(eta[i], k[i]);
+            // Snell's law with complex cosines covers absorption and total
+            // internal reflection inside the film alike.
+            let cos0 = Complex::real(cos_theta);
+            let cos1 = (Complex::real(1.0) - sin2 / (n1 * n1)).sqrt();
+            let cos2 = (Complex::real(1.0) - sin2 / (n2 * n2)).sqrt();
+            let phase =
+                (n1 * cos1 * (4.0 * f32::consts::PI * self.thickness / self.lambda[i])).exp_i();
+
+            let airy = |r01: Complex, r12: Complex| {
+                let r12 = r12 * phase;
+                ((r01 + r12) / (Complex::real(1.0) + r01 * r12)).norm_sqr()
+            };
+            let rs = |na: Complex, ca: Complex, nb: Complex, cb: Complex| {
+                (na * ca - nb * cb) / (na * ca + nb * cb)
+            };
+            let rp = |na: Complex, ca: Complex, nb: Complex, cb: Complex| {
+                (nb * ca - na * cb) / (nb * ca + na * cb)
+            };
+            let s = airy(rs(n0, cos0, n1, cos1), rs(n1, cos1, n2, cos2));
+            let p = airy(rp(n0, cos0, n1, cos1), rp(n1, cos1, n2, cos2));
+            ((s + p) / 2.0).clamp(0.0, 1.0)
+        }))
+    }
+}
+
+/// The complex numbers needed for the Fresnel equations of absorbing media.
+#[derive(Clone, Copy)]
+struct Complex {
+    re: f32,
+    im: f32,
+}
+
+impl Complex {
+    const fn new(re: f32, im: f32) -> Self {
+        Self { re, im }
+    }
+
+    const fn real(re: f32) -> Self {
+        Self::new(re, 0.0)
+    }
+
+    fn norm_sqr(self) -> f32 {
+        self.re * self.re + self.im * self.im
+    }
+
+    /// The principal square root, with a non-negative real part.
+    fn sqrt(self) -> Self {
+        let norm = self.norm_sqr().sqrt();
+        let re = f32::sqrt(((norm + self.re) / 2.0).max(0.0));
+        let im = f32::sqrt(((norm - self.re) / 2.0).max(0.0));
+        Self::new(re, if self.im < 0.0 { -im } else { im })
+    }
+
+    /// `exp(i self)`.
+    fn exp_i(self) -> Self {
+        let magnitude = f32::exp(-self.im);
+        Self::new(magnitude * self.re.cos(), magnitude * self.re.sin())
+    }
+}
+
+impl Add for Complex {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self::new(self.re + rhs.re, self.im + rhs.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Self::new(self.re - rhs.re, self.im - rhs.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        Self::new(
+            self.re * rhs.re - self.im * rhs.im,
+            self.re * rhs.im + self.im * rhs.re,
+        )
+    }
+}
+
+impl Mul<f32> for Complex {
+    type Output = Self;
+
+    fn mul(self, rhs: f32) -> Self {
+        Self::new(self.re * rhs, self.im * rhs)
+    }
+}
+
+impl Div for Complex {
+    type Output = Self;
+
+    fn div(self, rhs: Self) -> Self {
+        let denom = rhs.norm_sqr();
+        Self::new(
+            (self.re * rhs.re + self.im * rhs.im) / denom,
+            (self.im * rhs.re - self.re * rhs.im) / denom,
+        )
+    }
+}
+
+/// The direction of light transmitted through a facet with normal `n` that
+/// leaves along `w` on the other side, with `eta` as in
+/// `fresnel_dielectric`. `None` on total internal reflection.
+pub fn transmit(w: Vec3A, n: Vec3A, eta: f32) -> Option<Vec3A> {
+    let (cos_i, eta, n) = if w.dot(n) < 0.0 {
+        (-w.dot(n), 1.0 / eta, -n)
+    } else {
+        (w.dot(n), eta, n)
+    };
+    let sin2_t = (1.0 - cos_i * cos_i).max(0.0) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return None;
+    }
+    let cos_t = f32::sqrt(1.0 - sin2_t);
+    Some(-w / eta + (cos_i / eta - cos_t) * n)
+}
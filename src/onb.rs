@@ -1,5 +1,6 @@
 use glam::Vec3A;
 
+#[derive(Clone, Copy)]
 pub struct ONB {
     pub u: Vec3A,
     pub v: Vec3A,
@@ -19,7 +20,28 @@ impl ONB {
         Self { u, v, w }
     }
 
+    /// A basis with `w` along `n` and `u` along the part of `tangent`
+    /// perpendicular to it, falling back to `build_from_w` when `tangent`
+    /// is zero or parallel to `n`.
+    pub fn build_from_w_and_u(n: Vec3A, tangent: Vec3A) -> Self {
+        let w = n.normalize();
+        let u = (tangent - w * w.dot(tangent)).normalize_or_zero();
+        if u == Vec3A::ZERO {
+            return Self::build_from_w(n);
+        }
+        Self {
+            u,
+            v: w.cross(u),
+            w,
+        }
+    }
+
     pub fn local(&self, a: Vec3A) -> Vec3A {
         a.x * self.u + a.y * self.v + a.z * self.w
     }
+
+    /// The coordinates of `a` in this basis, the inverse of `local`.
+    pub fn to_local(&self, a: Vec3A) -> Vec3A {
+        Vec3A::new(a.dot(self.u), a.dot(self.v), a.dot(self.w))
+    }
 }
@@ -1,87 +1,59 @@
-use std::{f32, sync::Arc};
+use std::f32;
 
 use glam::Vec3A;
 
-use crate::{
-    hittable::Geometry,
-    onb::ONB,
-    rand,
-    vec::{Point3, Vec3Ext},
-};
+use crate::{onb::ONB, rand};
 
-pub trait PDF {
+pub trait PDF: Send + Sync {
     fn value(&self, direction: Vec3A) -> f32;
     fn generate(&self) -> Vec3A;
 }
 
-pub struct CosinePDF {
+/// Uniformly samples directions within a cone around `axis`.
+pub struct ConePDF {
     uvw: ONB,
+    cos_theta_max: f32,
 }
 
-impl CosinePDF {
-    pub fn new(w: Vec3A) -> Self {
+impl ConePDF {
+    pub fn new(axis: Vec3A, cos_theta_max: f32) -> Self {
         Self {
-            uvw: ONB::build_from_w(w),
+            uvw: ONB::build_from_w(axis),
+            cos_theta_max,
         }
     }
 }
 
-impl PDF for CosinePDF {
+impl PDF for ConePDF {
     fn value(&self, direction: Vec3A) -> f32 {
-        let cosine = direction.normalize().dot(self.uvw.w);
-        if cosine <= 0.0 {
+        if direction.normalize().dot(self.uvw.w) < self.cos_theta_max {
             0.0
         } else {
-            cosine / f32::consts::PI
+            1.0 / (2.0 * f32::consts::PI * (1.0 - self.cos_theta_max))
         }
     }
 
     fn generate(&self) -> Vec3A {
-        self.uvw.local(Vec3A::random_cosine_direction())
+        let r1: f32 = rand::random();
+        let r2: f32 = rand::random();
+        let z = 1.0 - r2 * (1.0 - self.cos_theta_max);
+        let phi = 2.0 * f32::consts::PI * r1;
+        let sin_theta = f32::sqrt(1.0 - z * z);
+        self.uvw
+            .local(Vec3A::new(phi.cos() * sin_theta, phi.sin() * sin_theta, z))
     }
 }
 
-pub struct HittablePDF {
-    geometry: Arc<Geometry>,
-    origin: Point3,
-}
-
-impl HittablePDF {
-    pub fn new(geometry: Arc<Geometry>, origin: Point3) -> Self {
-        Self { geometry, origin }
-    }
-}
-
-impl PDF for HittablePDF {
-    fn value(&self, direction: Vec3A) -> f32 {
-        self.geometry.pdf_value(self.origin, direction)
-    }
-
-    fn generate(&self) -> Vec3A {
-        self.geometry.random(self.origin)
-    }
-}
-
-pub struct MixturePDF {
-    p: [Arc<dyn PDF>; 2],
-}
-
-impl MixturePDF {
-    pub fn new(p0: Arc<dyn PDF>, p1: Arc<dyn PDF>) -> Self {
-        Self { p: [p0, p1] }
+/// Veach's power heuristic weight (beta = 2) for a sample drawn from `f_pdf`
+/// when the same direction could also have been drawn from `g_pdf`.
+pub fn power_heuristic(f_pdf: f32, g_pdf: f32) -> f32 {
+    let f = f_pdf * f_pdf;
+    let g = g_pdf * g_pdf;
+    if f.is_infinite() {
+        return 1.0;
     }
-}
-
-impl PDF for MixturePDF {
-    fn value(&self, direction: Vec3A) -> f32 {
-        0.5 * self.p[0].value(direction) + 0.5 * self.p[1].value(direction)
-    }
-
-    fn generate(&self) -> Vec3A {
-        if rand::random() {
-            self.p[0].generate()
-        } else {
-            self.p[1].generate()
-        }
+    if f + g <= 0.0 {
+        return 0.0;
     }
+    f / (f + g)
 }
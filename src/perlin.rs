@@ -0,0 +1,97 @@
+use glam::Vec3A;
+
+use crate::{rand, vec::Point3, vec::Vec3Ext};
+
+const POINT_COUNT: usize = 256;
+
+/// Perlin gradient noise over 3D space.
+pub struct Perlin {
+    gradients: Vec<Vec3A>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+impl Perlin {
+    pub fn new() -> Self {
+        Self {
+            gradients: (0..POINT_COUNT)
+                .map(|_| Vec3A::random_range(-1.0..1.0).normalize())
+                .collect(),
+            perm_x: generate_perm(),
+            perm_y: generate_perm(),
+            perm_z: generate_perm(),
+        }
+    }
+
+    /// Noise value in [-1, 1] at `p`.
+    pub fn noise(&self, p: Point3) -> f32 {
+        let floor = p.floor();
+        let uvw = p - floor;
+        let (i, j, k) = (floor.x as i32, floor.y as i32, floor.z as i32);
+
+        let mut c = [[[Vec3A::ZERO; 2]; 2]; 2];
+        for (di, plane) in c.iter_mut().enumerate() {
+            for (dj, row) in plane.iter_mut().enumerate() {
+                for (dk, gradient) in row.iter_mut().enumerate() {
+                    *gradient = self.gradients[self.perm_x[wrap(i + di as i32)]
+                        ^ self.perm_y[wrap(j + dj as i32)]
+                        ^ self.perm_z[wrap(k + dk as i32)]];
+                }
+            }
+        }
+
+        perlin_interp(&c, uvw)
+    }
+
+    /// Sum of `depth` octaves of the absolute noise value.
+    pub fn turbulence(&self, p: Point3, depth: u32) -> f32 {
+        let mut accum = 0.0;
+        let mut p = p;
+        let mut weight = 1.0;
+        for _ in 0..depth {
+            accum += weight * self.noise(p);
+            weight *= 0.5;
+            p *= 2.0;
+        }
+        accum.abs()
+    }
+}
+
+impl Default for Perlin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn wrap(i: i32) -> usize {
+    (i & (POINT_COUNT as i32 - 1)) as usize
+}
+
+fn generate_perm() -> Vec<usize> {
+    let mut p: Vec<usize> = (0..POINT_COUNT).collect();
+    for i in (1..POINT_COUNT).rev() {
+        let target = rand::random_range(0..=i);
+        p.swap(i, target);
+    }
+    p
+}
+
+fn perlin_interp(c: &[[[Vec3A; 2]; 2]; 2], uvw: Vec3A) -> f32 {
+    // Hermite smoothing removes the grid artifacts of linear interpolation.
+    let smooth = uvw * uvw * (3.0 - 2.0 * uvw);
+    let mut accum = 0.0;
+    for (i, plane) in c.iter().enumerate() {
+        for (j, row) in plane.iter().enumerate() {
+            for (k, gradient) in row.iter().enumerate() {
+                let (fi, fj, fk) = (i as f32, j as f32, k as f32);
+                let weight = uvw - Vec3A::new(fi, fj, fk);
+                accum += (fi * smooth.x + (1.0 - fi) * (1.0 - smooth.x))
+                    * (fj * smooth.y + (1.0 - fj) * (1.0 - smooth.y))
+                    * (fk * smooth.z + (1.0 - fk) * (1.0 - smooth.z))
+                    * gradient.dot(weight);
+            }
+        }
+    }
+    accum
+}
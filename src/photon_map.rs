@@ -0,0 +1,126 @@
+use glam::Vec3A;
+
+use crate::vec::{Color, Point3};
+
+#[derive(Clone, Copy)]
+pub struct Photon {
+    pub p: Point3,
+    /// Direction the photon was travelling in when it was stored.
+    pub direction: Vec3A,
+    pub power: Color,
+}
+
+/// A kd-tree over photons, stored implicitly: the median of each range is
+/// the node splitting it, with the left subtree before and the right one after.
+pub struct PhotonMap {
+    photons: Vec<Photon>,
+    axes: Vec<u8>,
+}
+
+impl PhotonMap {
+    pub fn build(mut photons: Vec<Photon>) -> Self {
+        let mut axes = vec![0; photons.len()];
+        build_range(&mut photons, &mut axes);
+        Self { photons, axes }
+    }
+
+    /// Calls `f` for every photon within `radius` of `p`.
+    pub fn for_each_within(&self, p: Point3, radius: f32, mut f: impl FnMut(&Photon)) {
+        self.search(0, self.photons.len(), p, radius * radius, &mut f);
+    }
+
+    fn search(
+        &self,
+        lo: usize,
+        hi: usize,
+        p: Point3,
+        radius_squared: f32,
+        f: &mut impl FnMut(&Photon),
+    ) {
+        if lo >= hi {
+            return;
+        }
+
+        let mid = (lo + hi) / 2;
+        let photon = &self.photons[mid];
+        if (photon.p - p).length_squared() <= radius_squared {
+            f(photon);
+        }
+
+        let axis = self.axes[mid] as usize;
+        let delta = p[axis] - photon.p[axis];
+        let (near, far) = if delta < 0.0 {
+            ((lo, mid), (mid + 1, hi))
+        } else {
+            ((mid + 1, hi), (lo, mid))
+        };
+        self.search(near.0, near.1, p, radius_squared, f);
+        if delta * delta <= radius_squared {
+            self.search(far.0, far.1, p, radius_squared, f);
+        }
+    }
+}
+
+fn build_range(photons: &mut [Photon], axes: &mut [u8]) {
+    if photons.is_empty() {
+        return;
+    }
+
+    let (min, max) = photons.iter().fold(
+        (
+            Point3::splat(f32::INFINITY),
+            Point3::splat(f32::NEG_INFINITY),
+        ),
+        |(min, max), photon| (min.min(photon.p), max.max(photon.p)),
+    );
+    let extent = max - min;
+    let axis = if extent.x > extent.y && extent.x > extent.z {
+        0
+    } else if extent.y > extent.z {
+        1
+    } else {
+        2
+    };
+
+    let mid = photons.len() / 2;
+    photons.select_nth_unstable_by(mid, |a, b| a.p[axis].total_cmp(&b.p[axis]));
+    axes[mid] = axis as u8;
+
+    let (left, rest) = photons.split_at_mut(mid);
+    let (left_axes, rest_axes) = axes.split_at_mut(mid);
+    build_range(left, left_axes);
+    build_range(&mut rest[1..], &mut rest_axes[1..]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{rand, vec::Vec3Ext};
+
+    #[test]
+    fn matches_brute_force_search() {
+        // Each photon's power holds its index, to tell the photons apart.
+        let photons: Vec<Photon> = (0..1000)
+            .map(|i| Photon {
+                p: Point3::random_range(-1.0..1.0) * Point3::new(1.0, 0.5, 2.0),
+                direction: Vec3A::X,
+                power: Color::splat(i as f32),
+            })
+            .collect();
+        let map = PhotonMap::build(photons.clone());
+
+        for _ in 0..100 {
+            let p = Point3::random_range(-1.5..1.5);
+            let radius = rand::random_range(0.0..0.8);
+            let mut found = vec![];
+            map.for_each_within(p, radius, |photon| found.push(photon.power.x as usize));
+            found.sort_unstable();
+            let expected: Vec<usize> = photons
+                .iter()
+                .filter(|photon| (photon.p - p).length_squared() <= radius * radius)
+                .map(|photon| photon.power.x as usize)
+                .collect();
+            assert_eq!(found, expected);
+        }
+    }
+}
@@ -1,7 +1,7 @@
 use std::cell::RefCell;
 
 use rand::{
-    Rng, SeedableRng,
+    Rng, RngCore, SeedableRng,
     distr::{
         Distribution, StandardUniform,
         uniform::{SampleRange, SampleUniform},
@@ -9,8 +9,39 @@ use rand::{
     rngs::SmallRng,
 };
 
+/// Where the random numbers of a thread come from.
+enum Source {
+    Small(SmallRng),
+    /// A generator installed by `with_rng`, e.g. to replay or mutate the
+    /// numbers driving a computation.
+    Custom(Box<dyn RngCore>),
+}
+
+impl RngCore for Source {
+    fn next_u32(&mut self) -> u32 {
+        match self {
+            Source::Small(rng) => rng.next_u32(),
+            Source::Custom(rng) => rng.next_u32(),
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        match self {
+            Source::Small(rng) => rng.next_u64(),
+            Source::Custom(rng) => rng.next_u64(),
+        }
+    }
+
+    fn fill_bytes(&mut self, dst: &mut [u8]) {
+        match self {
+            Source::Small(rng) => rng.fill_bytes(dst),
+            Source::Custom(rng) => rng.fill_bytes(dst),
+        }
+    }
+}
+
 thread_local! {
-    static RNG: RefCell<SmallRng> = RefCell::new(SmallRng::from_rng(&mut rand::rng()));
+    static RNG: RefCell<Source> = RefCell::new(Source::Small(SmallRng::from_rng(&mut rand::rng())));
 }
 
 pub fn random<T>() -> T
@@ -27,3 +58,21 @@ where
 {
     RNG.with_borrow_mut(|rng| rng.random_range(range))
 }
+
+/// Runs `f` with every random number drawn on this thread taken from `rng`.
+pub fn with_rng<T>(rng: impl RngCore + 'static, f: impl FnOnce() -> T) -> T {
+    let _restore = Restore(Some(RNG.replace(Source::Custom(Box::new(rng)))));
+    f()
+}
+
+/// Puts back the generator replaced by `with_rng` when dropped, even if the
+/// computation panics.
+struct Restore(Option<Source>);
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        if let Some(previous) = self.0.take() {
+            RNG.set(previous);
+        }
+    }
+}
@@ -2,6 +2,7 @@ use glam::Vec3A;
 
 use crate::vec::Point3;
 
+#[derive(Clone, Copy)]
 pub struct Ray {
     orig: Point3,
     dir: Vec3A,
@@ -0,0 +1,182 @@
+use std::f32;
+
+use glam::Vec3A;
+
+use crate::{
+    aabb::AABB,
+    background::Background,
+    hittable::{FreeFlightPdf, Geometry, HitRecord, HittableList},
+    material::Material,
+    medium_stack::MediumStack,
+    onb::ONB,
+    pdf::PDF,
+    rand,
+    ray::Ray,
+    vec::{Color, Point3, Vec3Ext},
+};
+
+/// Everything an integrator needs to know about the world being rendered.
+pub struct Scene {
+    world: HittableList,
+    lights: Option<Geometry>,
+    background: Background,
+    focus: Option<AABB>,
+}
+
+impl Scene {
+    /// `lights` holds the emitters that are sampled explicitly. They must also
+    /// be part of `world` to be visible.
+    pub fn new(world: HittableList, lights: HittableList, background: Background) -> Self {
+        Self {
+            world,
+            lights: (!lights.is_empty()).then_some(Geometry::List(lights)),
+            background,
+            focus: None,
+        }
+    }
+
+    /// Sets the region that light from the background is traced towards by
+    /// integrators starting paths from the lights, such as the part of a
+    /// large ground plane that is in view. Defaults to the bounds of the
+    /// world.
+    pub fn with_focus(self, focus: AABB) -> Self {
+        Self {
+            focus: Some(focus),
+            ..self
+        }
+    }
+
+    pub fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<(f32, HitRecord)> {
+        self.world.hit(ray, t_min, t_max)
+    }
+
+    /// Like `hit`, but tracking the dielectrics the path is inside in
+    /// `media`: false intersections of nested dielectrics are passed
+    /// through, and dielectric hits are given the refractive indices on
+    /// both sides. Also returns the fraction of light not absorbed by the
+    /// dielectrics on the way, including the weight of the hit.
+    pub fn hit_nested(&self, ray: &Ray, media: &mut MediumStack) -> Option<(HitRecord, Color)> {
+        let mut ray = *ray;
+        let mut transmittance = Color::ONE;
+        loop {
+            let (t, mut rec) = self.hit(&ray, 0.001, f32::INFINITY)?;
+            transmittance *= media.transmittance(t) * rec.weight();
+            let Material::Dielectric(dielectric) = rec.material() else {
+                return Some((rec, transmittance));
+            };
+            match media.interface(dielectric, rec.front_face()) {
+                Some((incident, transmitted)) => {
+                    rec.set_iors(incident, transmitted);
+                    return Some((rec, transmittance));
+                }
+                None => {
+                    media.cross(&rec, ray.direction(), ray.direction());
+                    ray = Ray::new(rec.point(), ray.direction());
+                }
+            }
+        }
+    }
+
+    /// The first surface along `ray`, passing through participating media,
+    /// together with the transmittance of the media up to it.
+    pub fn hit_through_media(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+    ) -> (Option<(f32, HitRecord)>, Color) {
+        let hit = self.world.hit_surface(ray, t_min, t_max);
+        let t_end = hit.as_ref().map_or(t_max, |(t, _)| *t);
+        (hit, self.world.transmittance(ray, t_min, t_end))
+    }
+
+    /// The densities of the media sampling the distance from `from` to `to`
+    /// along the way in `hit_nested`.
+    pub fn free_flight_pdf(&self, from: Point3, to: Point3) -> FreeFlightPdf {
+        let w = to - from;
+        let distance = w.length();
+        let ray = Ray::new(from, w / distance);
+        self.world.free_flight_pdf(&ray, 0.001, distance)
+    }
+
+    pub fn background(&self) -> &Background {
+        &self.background
+    }
+
+    /// Samples a direction from `origin` towards one of the lights, choosing
+    /// uniformly between the explicit lights and the sun.
+    pub fn sample_light(&self, origin: Point3) -> Option<Vec3A> {
+        match (&self.lights, self.background.sun_pdf()) {
+            (None, None) => None,
+            (Some(l), None) => Some(l.random(origin)),
+            (None, Some(s)) => Some(s.generate()),
+            (Some(l), Some(s)) => Some(if rand::random() {
+                l.random(origin)
+            } else {
+                s.generate()
+            }),
+        }
+    }
+
+    /// The solid angle density of `sample_light` for `direction`.
+    pub fn light_pdf(&self, origin: Point3, direction: Vec3A) -> f32 {
+        let sun = self.background.sun_pdf();
+        let count = self.lights.is_some() as usize + sun.is_some() as usize;
+        if count == 0 {
+            return 0.0;
+        }
+
+        let geometry = self
+            .lights
+            .as_ref()
+            .map_or(0.0, |l| l.pdf_value(origin, direction));
+        let sun = sun.map_or(0.0, |s| s.value(direction));
+        (geometry + sun) / count as f32
+    }
+
+    /// Samples a point on the surface of one of the explicit lights, returning
+    /// it with its area density.
+    pub fn sample_light_surface(&self) -> Option<(HitRecord, f32)> {
+        self.lights.as_ref()?.sample_surface()
+    }
+
+    /// The area density of `sample_light_surface` at `p`.
+    pub fn light_surface_pdf(&self, p: Point3) -> f32 {
+        self.lights.as_ref().map_or(0.0, |l| l.surface_pdf(p))
+    }
+
+    pub fn has_light_surfaces(&self) -> bool {
+        self.lights.is_some()
+    }
+
+    /// Samples a ray of light arriving from the background through the focus
+    /// region, returning it with the power it carries. The sun and the sky
+    /// are sampled with equal probability, and the sky below the horizon is
+    /// assumed to be hidden by the ground.
+    pub fn sample_background_ray(&self) -> Option<(Ray, Color)> {
+        let bounds = self.focus.clone().or_else(|| self.world.bounding_box())?;
+        let center = (bounds.min() + bounds.max()) / 2.0;
+        let radius = (bounds.max() - center).length();
+
+        let sun = self.background.sun_pdf();
+        let to_light = match &sun {
+            Some(sun) if rand::random() => sun.generate(),
+            _ => ONB::build_from_w(Vec3A::Y).local(Vec3A::random_cosine_direction()),
+        };
+        let sky_pdf = to_light.y.max(0.0) / f32::consts::PI;
+        let pdf_dir = match &sun {
+            Some(sun) => 0.5 * sun.value(to_light) + 0.5 * sky_pdf,
+            None => sky_pdf,
+        };
+        if pdf_dir < 1e-16 {
+            return None;
+        }
+
+        // Rays start on a disk covering the focus region, facing the light.
+        let offset = ONB::build_from_w(to_light).local(Vec3A::random_in_unit_disk());
+        let origin = center + radius * (to_light + offset);
+        let area = f32::consts::PI * radius * radius;
+        let power = self.background.value(to_light) * area / pdf_dir;
+        Some((Ray::new(origin, -to_light), power))
+    }
+}
@@ -0,0 +1,168 @@
+use std::f32;
+
+use glam::Vec3A;
+
+use crate::{
+    color,
+    pdf::ConePDF,
+    vec::{Color, Point3},
+};
+
+/// Scale from the model's luminance units (kcd/m^2) to scene radiance.
+const SKY_SCALE: f32 = 0.05;
+
+/// Luminance of the solar disk outside the atmosphere, in kcd/m^2.
+const SUN_LUMINANCE: f32 = 2.0e6;
+
+/// Mean angular radius of the sun as seen from the earth (0.2666 degrees).
+const SUN_ANGULAR_RADIUS: f32 = 0.004653;
+
+/// Wavelengths (in micrometers) standing in for the red, green and blue channels.
+const RGB_WAVELENGTHS: [f32; 3] = [0.65, 0.55, 0.45];
+
+/// Preetham, Shirley and Smits' analytic daylight model ("A Practical Analytic
+/// Model for Daylight", 1999) together with a sun disk of physical angular size.
+#[derive(Clone)]
+pub struct Sky {
+    sun_direction: Vec3A,
+    theta_s: f32,
+    perez_y: [f32; 5],
+    perez_x: [f32; 5],
+    perez_yy: [f32; 5],
+    zenith: Vec3A,
+    sun_radiance: Color,
+    cos_sun_radius: f32,
+}
+
+impl Sky {
+    /// `elevation` and `azimuth` are in degrees. The azimuth is measured from
+    /// +x towards +z, and turbidity is usually between 2 (clear) and 10 (hazy).
+    pub fn new(elevation: f32, azimuth: f32, turbidity: f32) -> Self {
+        let t = turbidity;
+        let elevation = elevation.to_radians();
+        let azimuth = azimuth.to_radians();
+        let theta_s = f32::consts::FRAC_PI_2 - elevation;
+
+        let sun_direction = Vec3A::new(
+            elevation.cos() * azimuth.cos(),
+            elevation.sin(),
+            elevation.cos() * azimuth.sin(),
+        );
+
+        let perez_yy = [
+            0.1787 * t - 1.4630,
+            -0.3554 * t + 0.4275,
+            -0.0227 * t + 5.3251,
+            0.1206 * t - 2.5771,
+            -0.0670 * t + 0.3703,
+        ];
+        let perez_x = [
+            -0.0193 * t - 0.2592,
+            -0.0665 * t + 0.0008,
+            -0.0004 * t + 0.2125,
+            -0.0641 * t - 0.8989,
+            -0.0033 * t + 0.0452,
+        ];
+        let perez_y = [
+            -0.0167 * t - 0.2608,
+            -0.0950 * t + 0.0092,
+            -0.0079 * t + 0.2102,
+            -0.0441 * t - 1.6537,
+            -0.0109 * t + 0.0529,
+        ];
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (f32::consts::PI - 2.0 * theta_s);
+        let zenith_luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+
+        let (th, th2, th3) = (theta_s, theta_s * theta_s, theta_s * theta_s * theta_s);
+        let zenith_x = t * t * (0.00166 * th3 - 0.00375 * th2 + 0.00209 * th)
+            + t * (-0.02903 * th3 + 0.06377 * th2 - 0.03202 * th + 0.00394)
+            + (0.11693 * th3 - 0.21196 * th2 + 0.06052 * th + 0.25886);
+        let zenith_y = t * t * (0.00275 * th3 - 0.00610 * th2 + 0.00317 * th)
+            + t * (-0.04214 * th3 + 0.08970 * th2 - 0.04153 * th + 0.00516)
+            + (0.15346 * th3 - 0.26756 * th2 + 0.06670 * th + 0.26688);
+
+        Self {
+            sun_direction,
+            theta_s,
+            perez_y,
+            perez_x,
+            perez_yy,
+            zenith: Vec3A::new(zenith_x, zenith_y, zenith_luminance.max(0.0)),
+            sun_radiance: sun_radiance(theta_s, t),
+            cos_sun_radius: SUN_ANGULAR_RADIUS.cos(),
+        }
+    }
+
+    pub fn sun_direction(&self) -> Vec3A {
+        self.sun_direction
+    }
+
+    /// Radiance arriving along `-direction`, including the sun disk.
+    pub fn value(&self, direction: Vec3A) -> Color {
+        let direction = direction.normalize();
+        let sky = self.sky_value(direction);
+        if self.sun_direction.y > -SUN_ANGULAR_RADIUS
+            && direction.dot(self.sun_direction) >= self.cos_sun_radius
+        {
+            sky + self.sun_radiance
+        } else {
+            sky
+        }
+    }
+
+    /// Samples directions inside the sun disk.
+    pub fn sun_pdf(&self) -> ConePDF {
+        ConePDF::new(self.sun_direction, self.cos_sun_radius)
+    }
+
+    fn sky_value(&self, direction: Vec3A) -> Color {
+        // The model is only valid above the horizon, so directions below it
+        // reuse the radiance at the horizon.
+        let cos_theta = direction.y.max(0.01);
+        let theta = cos_theta.acos();
+        let gamma = direction.dot(self.sun_direction).clamp(-1.0, 1.0).acos();
+
+        let x = self.zenith.x * perez(&self.perez_x, theta, gamma)
+            / perez(&self.perez_x, 0.0, self.theta_s);
+        let y = self.zenith.y * perez(&self.perez_y, theta, gamma)
+            / perez(&self.perez_y, 0.0, self.theta_s);
+        let luminance = self.zenith.z * perez(&self.perez_yy, theta, gamma)
+            / perez(&self.perez_yy, 0.0, self.theta_s);
+
+        if y <= 0.0 {
+            return Color::ZERO;
+        }
+        let xyz = Point3::new(x / y * luminance, luminance, (1.0 - x - y) / y * luminance);
+        color::xyz_to_rgb(xyz).max(Color::ZERO) * SKY_SCALE
+    }
+}
+
+/// Perez et al.'s sky luminance distribution function.
+fn perez(coeffs: &[f32; 5], theta: f32, gamma: f32) -> f32 {
+    let [a, b, c, d, e] = *coeffs;
+    let cos_gamma = gamma.cos();
+    (1.0 + a * f32::exp(b / theta.cos().max(0.01)))
+        * (1.0 + c * f32::exp(d * gamma) + e * cos_gamma * cos_gamma)
+}
+
+/// Sun radiance after Rayleigh and aerosol extinction along the optical path,
+/// following the appendix of the Preetham paper.
+fn sun_radiance(theta_s: f32, turbidity: f32) -> Color {
+    if theta_s > f32::consts::FRAC_PI_2 + SUN_ANGULAR_RADIUS {
+        return Color::ZERO;
+    }
+
+    let theta_deg = theta_s.to_degrees().min(93.0);
+    let relative_mass = 1.0 / (theta_s.cos().max(0.0) + 0.15 * (93.885 - theta_deg).powf(-1.253));
+    let beta = 0.04608 * turbidity - 0.04586;
+    let alpha = 1.3;
+
+    let [r, g, b] = RGB_WAVELENGTHS.map(|lambda| {
+        let rayleigh = f32::exp(-relative_mass * 0.008735 * lambda.powf(-4.08));
+        let aerosol = f32::exp(-relative_mass * beta * lambda.powf(-alpha));
+        rayleigh * aerosol
+    });
+
+    Color::new(r, g, b) * SUN_LUMINANCE * SKY_SCALE
+}
@@ -0,0 +1,251 @@
+//! Spectral rendering with hero wavelength sampling (Wilkie et al. 2014).
+//!
+//! While a set of wavelengths is active on a thread, the three lanes of every
+//! `Color` produced by materials and backgrounds hold spectral values at
+//! those wavelengths instead of RGB, so integrators carry spectra without
+//! any change. RGB inputs are upsampled with Smits' method and the result is
+//! converted back through CIE XYZ.
+
+use std::{cell::Cell, f32, sync::LazyLock};
+
+use glam::Vec3A;
+
+use crate::{color, rand, vec::Color};
+
+pub const LAMBDA_MIN: f32 = 380.0;
+pub const LAMBDA_MAX: f32 = 720.0;
+
+/// The dominant wavelengths of the red, green and blue primaries.
+const RGB_LAMBDA: [f32; 3] = [630.0, 532.0, 465.0];
+
+/// Number of wavelengths sampled together, one per lane of `Color`.
+const COUNT: usize = 3;
+
+thread_local! {
+    static WAVELENGTHS: Cell<Option<SampledWavelengths>> = const { Cell::new(None) };
+}
+
+/// Wavelengths in nanometers sampled for one path: a uniformly chosen hero
+/// wavelength and others rotated evenly through the visible range.
+#[derive(Clone, Copy)]
+pub struct SampledWavelengths {
+    lambda: [f32; COUNT],
+    /// Whether only the hero wavelength is still carried by the path.
+    terminated: bool,
+}
+
+impl SampledWavelengths {
+    pub fn sample() -> Self {
+        let u: f32 = rand::random();
+        let lambda = std::array::from_fn(|i| {
+            let offset = (u + i as f32 / COUNT as f32).fract();
+            LAMBDA_MIN + offset * (LAMBDA_MAX - LAMBDA_MIN)
+        });
+        Self {
+            lambda,
+            terminated: false,
+        }
+    }
+
+    /// The hero wavelength, which is the one kept when the others are
+    /// terminated by `terminate_secondary`.
+    pub fn hero(&self) -> f32 {
+        self.lambda[0]
+    }
+
+    pub fn lambda(&self) -> [f32; COUNT] {
+        self.lambda
+    }
+
+    pub fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+
+    /// Converts radiance sampled at these wavelengths to linear sRGB.
+    pub fn to_rgb(&self, spectrum: Color) -> Color {
+        let pdf = 1.0 / (LAMBDA_MAX - LAMBDA_MIN);
+        let count = if self.terminated { 1 } else { COUNT };
+        let xyz: Vec3A = self
+            .lambda
+            .iter()
+            .zip(spectrum.to_array())
+            .take(count)
+            .map(|(&lambda, value)| cie_xyz(lambda) * value / pdf)
+            .sum::<Vec3A>()
+            / count as f32;
+        let white = &*WHITE;
+        color::xyz_to_rgb(xyz / white.y_integral) / white.rgb
+    }
+}
+
+/// Runs `f` with the wavelengths of spectral rendering set to `wavelengths`.
+pub fn with_wavelengths<T>(wavelengths: SampledWavelengths, f: impl FnOnce() -> T) -> T {
+    let previous = WAVELENGTHS.replace(Some(wavelengths));
+    let result = f();
+    WAVELENGTHS.set(previous);
+    result
+}
+
+/// The wavelengths being rendered on this thread, `None` when rendering RGB.
+pub fn current() -> Option<SampledWavelengths> {
+    WAVELENGTHS.get()
+}
+
+/// Converts an RGB reflectance or emission to the representation currently
+/// rendered: unchanged in RGB mode, its upsampled spectrum at the current
+/// wavelengths in spectral mode.
+pub fn from_rgb(rgb: Color) -> Color {
+    match current() {
+        Some(wavelengths) => Color::from_array(wavelengths.lambda.map(|l| smits(rgb, l))),
+        None => rgb,
+    }
+}
+
+/// The wavelength in nanometers each lane of `Color` stands for: the current
+/// wavelengths in spectral mode, the dominant wavelengths of the primaries
+/// when rendering RGB.
+pub fn lambda() -> Color {
+    match current() {
+        Some(wavelengths) => Color::from_array(wavelengths.lambda),
+        None => Color::from_array(RGB_LAMBDA),
+    }
+}
+
+/// Converts a physical quantity given at the wavelengths of the primaries,
+/// such as the complex refractive index of a metal, to the representation
+/// currently rendered. In spectral mode it is interpolated linearly between
+/// those wavelengths and held constant beyond them; unlike `from_rgb`, this
+/// is valid for values outside [0, 1].
+pub fn from_rgb_samples(rgb: Color) -> Color {
+    match current() {
+        Some(wavelengths) => Color::from_array(wavelengths.lambda.map(|l| interpolate(rgb, l))),
+        None => rgb,
+    }
+}
+
+fn interpolate(rgb: Color, lambda: f32) -> f32 {
+    let [r, g, b] = rgb.to_array();
+    let [lambda_r, lambda_g, lambda_b] = RGB_LAMBDA;
+    let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t.clamp(0.0, 1.0);
+    if lambda < lambda_g {
+        lerp(b, g, (lambda - lambda_b) / (lambda_g - lambda_b))
+    } else {
+        lerp(g, r, (lambda - lambda_g) / (lambda_r - lambda_g))
+    }
+}
+
+/// Keeps only the hero wavelength for the rest of the current path, for
+/// events such as dispersion that send each wavelength in a different
+/// direction. Does nothing in RGB mode.
+pub fn terminate_secondary() {
+    WAVELENGTHS.set(current().map(|w| SampledWavelengths {
+        terminated: true,
+        ..w
+    }));
+}
+
+/// Values used to white balance the output, so that a constant spectrum of
+/// one maps to RGB (1, 1, 1).
+struct White {
+    y_integral: f32,
+    rgb: Color,
+}
+
+static WHITE: LazyLock<White> = LazyLock::new(|| {
+    let xyz: Vec3A = (LAMBDA_MIN as u32..LAMBDA_MAX as u32)
+        .map(|l| cie_xyz(l as f32 + 0.5))
+        .sum();
+    White {
+        y_integral: xyz.y,
+        rgb: color::xyz_to_rgb(xyz / xyz.y),
+    }
+});
+
+/// The CIE 1931 color matching functions, using the multi-lobe gaussian fit
+/// of Wyman, Sloan and Shirley (2013).
+pub fn cie_xyz(lambda: f32) -> Vec3A {
+    let g = |mu: f32, sigma1: f32, sigma2: f32| {
+        let t = (lambda - mu) / if lambda < mu { sigma1 } else { sigma2 };
+        f32::exp(-0.5 * t * t)
+    };
+    Vec3A::new(
+        1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2),
+        0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1),
+        1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8),
+    )
+}
+
+/// Basis spectra of Smits (1999) over ten equal bins from `LAMBDA_MIN` to
+/// `LAMBDA_MAX`.
+const SMITS_WHITE: [f32; 10] = [
+    1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000,
+];
+const SMITS_CYAN: [f32; 10] = [
+    0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000,
+];
+const SMITS_MAGENTA: [f32; 10] = [
+    1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959,
+];
+const SMITS_YELLOW: [f32; 10] = [
+    0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840,
+];
+const SMITS_RED: [f32; 10] = [
+    0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149,
+];
+const SMITS_GREEN: [f32; 10] = [
+    0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025,
+];
+const SMITS_BLUE: [f32; 10] = [
+    1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496,
+];
+
+/// Evaluates Smits' spectrum for `rgb` at `lambda`, interpolating linearly
+/// between the centers of the bins.
+fn smits(rgb: Color, lambda: f32) -> f32 {
+    let x = (lambda - LAMBDA_MIN) / (LAMBDA_MAX - LAMBDA_MIN) * 10.0 - 0.5;
+    let i = (x.floor() as isize).clamp(0, 8) as usize;
+    let t = (x - i as f32).clamp(0.0, 1.0);
+    let basis = |b: &[f32; 10]| b[i] * (1.0 - t) + b[i + 1] * t;
+
+    let (r, g, b) = (rgb.x, rgb.y, rgb.z);
+    if r <= g && r <= b {
+        r * basis(&SMITS_WHITE)
+            + if g <= b {
+                (g - r) * basis(&SMITS_CYAN) + (b - g) * basis(&SMITS_BLUE)
+            } else {
+                (b - r) * basis(&SMITS_CYAN) + (g - b) * basis(&SMITS_GREEN)
+            }
+    } else if g <= r && g <= b {
+        g * basis(&SMITS_WHITE)
+            + if r <= b {
+                (r - g) * basis(&SMITS_MAGENTA) + (b - r) * basis(&SMITS_BLUE)
+            } else {
+                (b - g) * basis(&SMITS_MAGENTA) + (r - b) * basis(&SMITS_RED)
+            }
+    } else {
+        b * basis(&SMITS_WHITE)
+            + if r <= g {
+                (r - b) * basis(&SMITS_YELLOW) + (g - r) * basis(&SMITS_GREEN)
+            } else {
+                (g - b) * basis(&SMITS_YELLOW) + (r - g) * basis(&SMITS_RED)
+            }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_rgb_samples_interpolates_between_primaries() {
+        let rgb = Color::new(0.2, 1.0, 4.0);
+        assert_eq!(from_rgb_samples(rgb), rgb);
+
+        let wavelengths = SampledWavelengths {
+            lambda: [630.0, 498.5, 700.0],
+            terminated: false,
+        };
+        let value = with_wavelengths(wavelengths, || from_rgb_samples(rgb));
+        assert!(value.abs_diff_eq(Color::new(0.2, 2.5, 0.2), 1e-6));
+    }
+}
@@ -3,7 +3,13 @@ use std::f32;
 use glam::Vec3A;
 
 use crate::{
-    aabb::AABB, hittable::HitRecord, material::Material, onb::ONB, rand, ray::Ray, vec::Point3,
+    aabb::AABB,
+    hittable::HitRecord,
+    material::Material,
+    onb::ONB,
+    rand,
+    ray::Ray,
+    vec::{Point3, Vec3Ext},
 };
 
 #[derive(Clone)]
@@ -95,6 +101,26 @@ impl Sphere {
         uvw.local(random_to_sphere(self.radius, direction.length_squared()))
     }
 
+    /// Samples a point uniformly on the surface, returning it with its outward
+    /// normal and the area density.
+    pub fn sample_surface(&self) -> (HitRecord, f32) {
+        let normal = Vec3A::random_unit();
+        let p = self.center + self.radius * normal;
+        let (u, v) = Sphere::calculate_uv(p);
+        (
+            HitRecord::new(p, normal, self.material.clone(), u, v, true)
+                .with_dpdu(self.dpdu(normal)),
+            self.surface_pdf(p),
+        )
+    }
+
+    pub fn surface_pdf(&self, p: Point3) -> f32 {
+        if ((p - self.center).length() - self.radius).abs() > 1e-3 * self.radius {
+            return 0.0;
+        }
+        1.0 / (4.0 * f32::consts::PI * self.radius * self.radius)
+    }
+
     fn calculate_uv(p: Point3) -> (f32, f32) {
         let pi = f32::consts::PI;
         let phi = f32::atan2(p.z, p.x);
@@ -104,6 +130,12 @@ impl Sphere {
         (u, v)
     }
 
+    /// The derivative of the point with outward normal `normal` with respect
+    /// to `u`, running along the parallels.
+    fn dpdu(&self, normal: Vec3A) -> Vec3A {
+        2.0 * f32::consts::PI * self.radius * Vec3A::new(normal.z, 0.0, -normal.x)
+    }
+
     fn hit_record(&self, ray: &Ray, t: f32) -> HitRecord {
         let p = ray.at(t);
         let outward_normal = (p - self.center) / self.radius;
@@ -121,6 +153,7 @@ impl Sphere {
             v,
             front_face,
         )
+        .with_dpdu(self.dpdu(outward_normal))
     }
 }
 
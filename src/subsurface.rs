@@ -0,0 +1,187 @@
+use std::sync::Arc;
+
+use glam::Vec3A;
+
+use crate::{
+    aabb::AABB,
+    hittable::{FreeFlightPdf, Geometry, HitRecord},
+    material::{HenyeyGreenstein, Material},
+    rand,
+    ray::Ray,
+    spectrum,
+    texture::Texture,
+    vec::{Color, Point3},
+};
+
+/// A closed object of translucent material such as skin, wax, marble or
+/// milk, rendered with a volumetric random walk.
+///
+/// Light crosses the surface of the boundary as its own material decides,
+/// typically a `Dielectric` or a `RoughDielectric`, the latter letting
+/// integrators sample lights where walks leave the object. Inside, it
+/// scatters through a homogeneous medium until it leaves again. Unlike
+/// `ConstantMedium`, the boundary may be concave.
+pub struct Subsurface {
+    boundary: Box<Geometry>,
+    mean_free_path: Color,
+    /// The single scattering albedo inside.
+    albedo: Arc<dyn Texture>,
+    phase_function: Material,
+}
+
+impl Subsurface {
+    /// `albedo` is the color of the object where it is thick enough for no
+    /// light to pass through, and `mean_free_path` the average distance
+    /// light of each color travels inside between scattering events.
+    pub fn new(boundary: Geometry, albedo: Arc<dyn Texture>, mean_free_path: Color) -> Self {
+        let albedo: Arc<dyn Texture> = Arc::new(SingleScatteringAlbedo(albedo));
+        Self {
+            boundary: Box::new(boundary),
+            mean_free_path,
+            phase_function: Material::HenyeyGreenstein(HenyeyGreenstein::new(albedo.clone(), 0.0)),
+            albedo,
+        }
+    }
+
+    /// Scatters light forward inside for positive `g`, as in skin, and
+    /// backward for negative `g`.
+    pub fn with_anisotropy(self, g: f32) -> Self {
+        let phase_function = HenyeyGreenstein::new(self.albedo.clone(), g);
+        Self {
+            phase_function: Material::HenyeyGreenstein(phase_function),
+            ..self
+        }
+    }
+
+    /// The extinction coefficient at the current wavelengths.
+    fn sigma_t(&self) -> Color {
+        1.0 / spectrum::from_rgb(self.mean_free_path).max(Color::splat(1e-6))
+    }
+
+    /// The exit of `ray` from the inside of the boundary, `None` if it
+    /// starts outside.
+    fn exit(&self, ray: &Ray, t_min: f32) -> Option<f32> {
+        let (t, rec) = self.boundary.hit(ray, t_min, f32::INFINITY)?;
+        (!rec.front_face()).then_some(t)
+    }
+
+    pub fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<(f32, HitRecord)> {
+        let (t_surface, rec) = self.boundary.hit(ray, t_min, f32::INFINITY)?;
+        if rec.front_face() {
+            return (t_surface < t_max).then_some((t_surface, rec));
+        }
+
+        // Sample the distance with the extinction of a random channel, and
+        // weight by the one-sample MIS density over all channels.
+        let sigma_t = self.sigma_t();
+        let channel = rand::random_range(0..3);
+        let t = t_min - f32::ln(1.0 - rand::random::<f32>()) / sigma_t[channel];
+        if t < t_surface.min(t_max) {
+            let transmittance = (-sigma_t * (t - t_min)).exp();
+            let pdf = (sigma_t * transmittance).element_sum() / 3.0;
+            // The normal and orientation are arbitrary inside a medium.
+            let rec = HitRecord::new(
+                ray.at(t),
+                Vec3A::X,
+                self.phase_function.clone(),
+                0.0,
+                0.0,
+                true,
+            )
+            .with_weight(sigma_t * transmittance / pdf);
+            return Some((t, rec));
+        }
+        if t_surface >= t_max {
+            return None;
+        }
+        let transmittance = (-sigma_t * (t_surface - t_min)).exp();
+        let probability = transmittance.element_sum() / 3.0;
+        Some((t_surface, rec.with_weight(transmittance / probability)))
+    }
+
+    pub fn hit_surface(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<(f32, HitRecord)> {
+        self.boundary.hit_surface(ray, t_min, t_max)
+    }
+
+    /// The probability of `ray` passing through the inside between `t_min`
+    /// and `t_max`.
+    pub fn transmittance(&self, ray: &Ray, t_min: f32, t_max: f32) -> Color {
+        self.exit(ray, t_min).map_or(Color::ONE, |t_exit| {
+            (-self.sigma_t() * (t_exit.min(t_max) - t_min).max(0.0)).exp()
+        })
+    }
+
+    /// The densities of the one-sample MIS over the channels in `hit`.
+    pub fn free_flight_pdf(&self, ray: &Ray, t_min: f32, t_max: f32) -> FreeFlightPdf {
+        let Some(t_exit) = self.exit(ray, t_min) else {
+            return FreeFlightPdf::VACUUM;
+        };
+        let sigma_t = self.sigma_t();
+        let transmittance = (-sigma_t * (t_exit.min(t_max) - t_min).max(0.0)).exp();
+        let scatter = if t_max < t_exit {
+            (sigma_t * transmittance).element_sum() / 3.0
+        } else {
+            0.0
+        };
+        FreeFlightPdf {
+            pass: transmittance.element_sum() / 3.0,
+            scatter,
+        }
+    }
+
+    pub fn bounding_box(&self) -> Option<AABB> {
+        self.boundary.bounding_box()
+    }
+}
+
+/// The albedo of single scattering events that gives the wrapped albedo
+/// after multiple scattering in a thick slab, using the fit of Chiang et al.
+/// (2016).
+struct SingleScatteringAlbedo(Arc<dyn Texture>);
+
+impl Texture for SingleScatteringAlbedo {
+    fn value(&self, u: f32, v: f32, p: Point3) -> Color {
+        self.0
+            .value(u, v, p)
+            .clamp(Color::ZERO, Color::ONE)
+            .map(|a| {
+                1.0 - (4.09712 + 4.20863 * a - f32::sqrt(9.59217 + 41.6808 * a + 17.7126 * a * a))
+                    .powi(2)
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{material::Lambertian, sphere::Sphere, texture::SolidTexture, vec::Vec3Ext};
+
+    #[test]
+    fn free_flight_weights_are_unbiased() {
+        let albedo: Arc<dyn Texture> = Arc::new(SolidTexture::new(0.8, 0.8, 0.8));
+        let boundary = Geometry::Sphere(Sphere::new(
+            Point3::ZERO,
+            1.0,
+            Material::Lambertian(Lambertian::new(albedo.clone())),
+        ));
+        let mean_free_path = Color::new(0.5, 1.0, 2.0);
+        let subsurface = Subsurface::new(boundary, albedo, mean_free_path);
+
+        // From the center, the surface is reached with probability T and
+        // light scatters on the way with probability 1 - T per channel.
+        const N: usize = 200_000;
+        let (mut escaped, mut scattered) = (Color::ZERO, Color::ZERO);
+        for _ in 0..N {
+            let ray = Ray::new(Point3::ZERO, Vec3A::random_unit());
+            let (_, rec) = subsurface.hit(&ray, 0.0, f32::INFINITY).unwrap();
+            if rec.material().is_medium() {
+                scattered += rec.weight();
+            } else {
+                escaped += rec.weight();
+            }
+        }
+        let transmittance = (-1.0 / mean_free_path).exp();
+        assert!((escaped / N as f32).abs_diff_eq(transmittance, 0.01));
+        assert!((scattered / N as f32).abs_diff_eq(1.0 - transmittance, 0.01));
+    }
+}
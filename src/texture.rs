@@ -1,4 +1,7 @@
-use crate::vec::{Color, Point3};
+use crate::{
+    perlin::Perlin,
+    vec::{Color, Point3},
+};
 
 pub trait Texture: Send + Sync {
     fn value(&self, u: f32, v: f32, p: Point3) -> Color;
@@ -23,3 +26,25 @@ impl From<Color> for SolidTexture {
         Self(value)
     }
 }
+
+/// Gray turbulence, e.g. as the density of a cloud.
+pub struct NoiseTexture {
+    noise: Perlin,
+    scale: f32,
+}
+
+impl NoiseTexture {
+    /// `scale` is the frequency of the noise in world space.
+    pub fn new(scale: f32) -> Self {
+        Self {
+            noise: Perlin::new(),
+            scale,
+        }
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, _: f32, _: f32, p: Point3) -> Color {
+        Color::splat(self.noise.turbulence(self.scale * p, 7))
+    }
+}
@@ -0,0 +1,311 @@
+//! Dense voxel grids of densities, e.g. exported from a smoke simulation.
+//!
+//! Grids are stored in a simple binary format, with all values little-endian:
+//!
+//! | field      | type              | description                                |
+//! |------------|-------------------|--------------------------------------------|
+//! | magic      | 4 bytes           | `b"VOXG"`                                  |
+//! | resolution | 3 × `u32`         | number of voxels along x, y and z          |
+//! | bounds     | 6 × `f32`         | minimum then maximum corner in world space |
+//! | densities  | x × y × z × `f32` | voxel values, x varying fastest, then y    |
+//!
+//! Each value is the density at the center of its voxel.
+
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use glam::Vec3A;
+
+use crate::{
+    aabb::AABB,
+    texture::Texture,
+    vec::{Color, Point3},
+};
+
+const MAGIC: &[u8; 4] = b"VOXG";
+
+/// Densities on a regular grid spanning an axis-aligned box, trilinearly
+/// interpolated between voxel centers and zero outside the box.
+pub struct VoxelGrid {
+    resolution: [usize; 3],
+    bounds: AABB,
+    densities: Vec<f32>,
+    max_density: f32,
+}
+
+impl VoxelGrid {
+    /// `densities` holds one value per voxel in the order of the file format.
+    pub fn new(resolution: [usize; 3], bounds: AABB, densities: Vec<f32>) -> Self {
+        assert!(
+            resolution.iter().all(|&n| n > 0),
+            "grid resolution must be positive"
+        );
+        assert_eq!(
+            densities.len(),
+            resolution.iter().product::<usize>(),
+            "density count does not match the grid resolution"
+        );
+        assert!(
+            is_valid_bounds(&bounds),
+            "grid bounds must be finite and non-empty"
+        );
+        assert!(
+            densities.iter().all(|d| d.is_finite() && *d >= 0.0),
+            "densities must be finite and non-negative"
+        );
+        let max_density = densities.iter().copied().fold(0.0, f32::max);
+        Self {
+            resolution,
+            bounds,
+            densities,
+            max_density,
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::read(BufReader::new(File::open(path)?))
+    }
+
+    pub fn read(mut r: impl Read) -> io::Result<Self> {
+        let mut magic = [0; 4];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not a voxel grid file"));
+        }
+
+        let mut resolution = [0; 3];
+        for n in &mut resolution {
+            *n = read_u32(&mut r)? as usize;
+        }
+        let mut corners = [0.0; 6];
+        for c in &mut corners {
+            *c = read_f32(&mut r)?;
+        }
+
+        if resolution.contains(&0) {
+            return Err(invalid_data("grid resolution must be positive"));
+        }
+        let bounds = AABB::new(
+            Point3::from_slice(&corners[..3]),
+            Point3::from_slice(&corners[3..]),
+        );
+        if !is_valid_bounds(&bounds) {
+            return Err(invalid_data("grid bounds must be finite and non-empty"));
+        }
+
+        let bytes = resolution
+            .iter()
+            .try_fold(4usize, |acc, &n| acc.checked_mul(n))
+            .ok_or_else(|| invalid_data("grid is too large"))?;
+        // Grow the buffer with the data actually read rather than trusting
+        // the header with the allocation.
+        let mut data = vec![];
+        r.take(bytes as u64).read_to_end(&mut data)?;
+        if data.len() < bytes {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "voxel grid file is truncated",
+            ));
+        }
+        let densities: Vec<f32> = data
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+        if !densities.iter().all(|d| d.is_finite() && *d >= 0.0) {
+            return Err(invalid_data("densities must be finite and non-negative"));
+        }
+
+        Ok(Self::new(resolution, bounds, densities))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut w = BufWriter::new(File::create(path)?);
+        self.write(&mut w)?;
+        w.flush()
+    }
+
+    pub fn write(&self, w: &mut impl Write) -> io::Result<()> {
+        w.write_all(MAGIC)?;
+        for n in self.resolution {
+            w.write_all(&(n as u32).to_le_bytes())?;
+        }
+        for c in self
+            .bounds
+            .min()
+            .to_array()
+            .into_iter()
+            .chain(self.bounds.max().to_array())
+        {
+            w.write_all(&c.to_le_bytes())?;
+        }
+        for d in &self.densities {
+            w.write_all(&d.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    pub fn bounding_box(&self) -> AABB {
+        self.bounds.clone()
+    }
+
+    /// The largest density in the grid, which bounds the interpolated values.
+    pub fn max_density(&self) -> f32 {
+        self.max_density
+    }
+
+    fn voxel(&self, x: usize, y: usize, z: usize) -> f32 {
+        let [nx, ny, _] = self.resolution;
+        self.densities[(z * ny + y) * nx + x]
+    }
+
+    /// The trilinearly interpolated density at `p`.
+    pub fn density(&self, p: Point3) -> f32 {
+        let (min, max) = (self.bounds.min(), self.bounds.max());
+        let size = Vec3A::from_array(self.resolution.map(|n| n as f32));
+        let local = (p - min) / (max - min);
+        if local.cmplt(Vec3A::ZERO).any() || local.cmpgt(Vec3A::ONE).any() {
+            return 0.0;
+        }
+
+        // Position in voxel units relative to the center of the first voxel.
+        let g = (local * size - 0.5).max(Vec3A::ZERO);
+        let base = g.floor();
+        let t = g - base;
+        let lo = base.to_array().map(|c| c as usize);
+        let mut corner = [[0; 2]; 3];
+        for axis in 0..3 {
+            let last = self.resolution[axis] - 1;
+            corner[axis] = [lo[axis].min(last), (lo[axis] + 1).min(last)];
+        }
+
+        let mut density = 0.0;
+        for (dz, &z) in corner[2].iter().enumerate() {
+            for (dy, &y) in corner[1].iter().enumerate() {
+                for (dx, &x) in corner[0].iter().enumerate() {
+                    let weight = lerp_weight(t.x, dx) * lerp_weight(t.y, dy) * lerp_weight(t.z, dz);
+                    density += weight * self.voxel(x, y, z);
+                }
+            }
+        }
+        density
+    }
+}
+
+impl Texture for VoxelGrid {
+    fn value(&self, _: f32, _: f32, p: Point3) -> Color {
+        Color::splat(self.density(p))
+    }
+}
+
+fn lerp_weight(t: f32, corner: usize) -> f32 {
+    if corner == 0 { 1.0 - t } else { t }
+}
+
+fn is_valid_bounds(bounds: &AABB) -> bool {
+    bounds.min().is_finite() && bounds.max().is_finite() && bounds.min().cmplt(bounds.max()).all()
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn read_u32(r: &mut impl Read) -> io::Result<u32> {
+    let mut b = [0; 4];
+    r.read_exact(&mut b)?;
+    Ok(u32::from_le_bytes(b))
+}
+
+fn read_f32(r: &mut impl Read) -> io::Result<f32> {
+    let mut b = [0; 4];
+    r.read_exact(&mut b)?;
+    Ok(f32::from_le_bytes(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid() -> VoxelGrid {
+        let bounds = AABB::new(Point3::new(-1.0, 0.0, 2.0), Point3::new(1.0, 3.0, 4.0));
+        let densities = (0..24).map(|i| i as f32 * 0.5).collect();
+        VoxelGrid::new([2, 3, 4], bounds, densities)
+    }
+
+    fn header(resolution: [u32; 3], corners: [f32; 6]) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(resolution.iter().flat_map(|n| n.to_le_bytes()));
+        bytes.extend(corners.iter().flat_map(|c| c.to_le_bytes()));
+        bytes
+    }
+
+    #[test]
+    fn round_trip() {
+        let grid = grid();
+        let mut bytes = vec![];
+        grid.write(&mut bytes).unwrap();
+        let read = VoxelGrid::read(bytes.as_slice()).unwrap();
+        assert_eq!(read.resolution, grid.resolution);
+        assert_eq!(read.bounds.min(), grid.bounds.min());
+        assert_eq!(read.bounds.max(), grid.bounds.max());
+        assert_eq!(read.densities, grid.densities);
+        assert_eq!(read.max_density(), grid.max_density());
+    }
+
+    #[test]
+    fn truncated() {
+        let mut bytes = vec![];
+        grid().write(&mut bytes).unwrap();
+        for len in [2, 20, bytes.len() - 1] {
+            let err = VoxelGrid::read(&bytes[..len]).err().unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        }
+    }
+
+    #[test]
+    fn huge_header_without_data() {
+        let bytes = header([65535; 3], [0.0, 0.0, 0.0, 1.0, 1.0, 1.0]);
+        let err = VoxelGrid::read(bytes.as_slice()).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn invalid_bounds() {
+        for corners in [
+            [0.0, 0.0, 0.0, f32::NAN, 1.0, 1.0],
+            [0.0, 0.0, 0.0, 1.0, -1.0, 1.0],
+            [0.0, 0.0, 0.0, 1.0, 1.0, 0.0],
+        ] {
+            let mut bytes = header([1; 3], corners);
+            bytes.extend(1.0f32.to_le_bytes());
+            let err = VoxelGrid::read(bytes.as_slice()).err().unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "grid bounds must be finite and non-empty")]
+    fn new_rejects_inverted_bounds() {
+        let bounds = AABB::new(Point3::ONE, Point3::ZERO);
+        VoxelGrid::new([1; 3], bounds, vec![1.0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "grid bounds must be finite and non-empty")]
+    fn new_rejects_flat_bounds() {
+        let bounds = AABB::new(Point3::ZERO, Point3::new(1.0, 0.0, 1.0));
+        VoxelGrid::new([1; 3], bounds, vec![1.0]);
+    }
+
+    #[test]
+    fn invalid_densities() {
+        for density in [f32::NAN, -1.0, f32::INFINITY] {
+            let mut bytes = header([1; 3], [0.0, 0.0, 0.0, 1.0, 1.0, 1.0]);
+            bytes.extend(density.to_le_bytes());
+            let err = VoxelGrid::read(bytes.as_slice()).err().unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+}